@@ -1,51 +1,205 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::error;
 use std::fs::File;
-use std::io::BufRead;
+use std::io::{self, BufRead, Write};
 use std::{io::BufReader, path::Path};
 
 type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 pub fn compute(p: &Path, n: usize) -> Result<i64> {
-    let f = File::open(p)?;
-    let lines = BufReader::new(f).lines();
+    compute_reader(BufReader::new(File::open(p)?), n)
+}
+
+// Streams the input and keeps only the n largest totals, so memory does not
+// grow with the number of elves.
+pub fn compute_reader<R: BufRead>(r: R, n: usize) -> Result<i64> {
     let mut max = Max::new(n);
-    let mut sum = 0;
-    for line in lines {
-        let ln = line?;
+    for_each_elf(r, |e| max.update(e.total))?;
+    Ok(max.sum())
+}
+
+// Calls f for every elf in turn. A blank line ends an elf, and a trailing one
+// does not start another.
+fn for_each_elf<R: BufRead, F: FnMut(Elf)>(mut r: R, mut f: F) -> Result<()> {
+    let mut line = String::new();
+    let (mut index, mut total, mut items) = (1, 0, 0);
+    for no in 1.. {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            break;
+        }
+        let ln = line.trim_end_matches(['\n', '\r']);
         if ln.is_empty() {
-            max.update(sum);
-            sum = 0;
+            f(Elf {
+                index,
+                total,
+                items,
+            });
+            (index, total, items) = (index + 1, 0, 0);
         } else {
-            sum += ln.parse::<i64>()?;
+            let v = ln
+                .parse::<i64>()
+                .map_err(|e| format!("line {}: invalid calories {:?}: {}", no, ln, e))?;
+            total += v;
+            items += 1;
         }
     }
-    max.update(sum);
-    Ok(max.sum())
+    if items > 0 {
+        f(Elf {
+            index,
+            total,
+            items,
+        });
+    }
+    Ok(())
+}
+
+// An elf's position in the input, counting from 1 as the puzzle does, and
+// what it carries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Elf {
+    pub index: usize,
+    pub total: i64,
+    pub items: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub empty: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub elves: Vec<Elf>,
+    // the top elves by total, ties broken by the lower index
+    pub top: Vec<Elf>,
+    pub stats: Stats,
+    totals: Vec<i64>,
+}
+
+const PERCENTILES: [usize; 5] = [10, 25, 50, 75, 90];
+
+pub fn report(p: &Path, n: usize) -> Result<Report> {
+    Ok(Report::new(load(p)?, n))
+}
+
+fn load(p: &Path) -> Result<Vec<Elf>> {
+    let mut elves = Vec::new();
+    for_each_elf(BufReader::new(File::open(p)?), |e| elves.push(e))?;
+    Ok(elves)
+}
+
+impl Report {
+    fn new(elves: Vec<Elf>, n: usize) -> Self {
+        let mut top = elves.clone();
+        top.sort_by(|a, b| b.total.cmp(&a.total).then(a.index.cmp(&b.index)));
+        top.truncate(n);
+        let mut totals = elves.iter().map(|e| e.total).collect::<Vec<_>>();
+        totals.sort();
+        let count = totals.len();
+        let median = match count {
+            0 => 0.0,
+            _ if count % 2 == 1 => totals[count / 2] as f64,
+            _ => (totals[count / 2 - 1] + totals[count / 2]) as f64 / 2.0,
+        };
+        let stats = Stats {
+            count,
+            mean: match count {
+                0 => 0.0,
+                _ => totals.iter().sum::<i64>() as f64 / count as f64,
+            },
+            median,
+            empty: elves.iter().filter(|e| e.items == 0).count(),
+        };
+        Report {
+            elves,
+            top,
+            stats,
+            totals,
+        }
+    }
+
+    // Nearest-rank percentile of the totals, for 0 < q <= 100.
+    pub fn percentile(&self, q: usize) -> Option<i64> {
+        if q == 0 || q > 100 {
+            return None;
+        }
+        let rank = (q * self.totals.len()).div_ceil(100);
+        self.totals.get(rank.checked_sub(1)?).copied()
+    }
+
+    pub fn write_table<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(
+            w,
+            "{:>6} {:>6} {:>10} {:>6}",
+            "rank", "elf", "total", "items"
+        )?;
+        for (i, e) in self.top.iter().enumerate() {
+            writeln!(
+                w,
+                "{:>6} {:>6} {:>10} {:>6}",
+                i + 1,
+                e.index,
+                e.total,
+                e.items
+            )?;
+        }
+        writeln!(w)?;
+        writeln!(w, "{:<8} {:>12}", "count", self.stats.count)?;
+        writeln!(w, "{:<8} {:>12.2}", "mean", self.stats.mean)?;
+        writeln!(w, "{:<8} {:>12.1}", "median", self.stats.median)?;
+        for q in PERCENTILES {
+            if let Some(v) = self.percentile(q) {
+                writeln!(w, "{:<8} {:>12}", format!("p{}", q), v)?;
+            }
+        }
+        writeln!(w, "{:<8} {:>12}", "empty", self.stats.empty)
+    }
+
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "rank,elf,total,items")?;
+        for (i, e) in self.top.iter().enumerate() {
+            writeln!(w, "{},{},{},{}", i + 1, e.index, e.total, e.items)?;
+        }
+        Ok(())
+    }
 }
 
+// The n largest values seen so far, in a min-heap so that each update is
+// O(log n).
 struct Max {
-    max: Vec<i64>,
+    len: usize,
+    max: BinaryHeap<Reverse<i64>>,
 }
 
 impl Max {
     pub fn new(len: usize) -> Self {
-        Max { max: vec![0; len] }
+        Max {
+            len,
+            max: BinaryHeap::new(),
+        }
     }
     pub fn update(&mut self, v: i64) {
-        if let Some(min) = self.max.iter_mut().min() {
-            if v > *min {
-                *min = v
+        if self.max.len() < self.len {
+            self.max.push(Reverse(v));
+        } else if let Some(mut min) = self.max.peek_mut() {
+            if v > min.0 {
+                *min = Reverse(v)
             }
         }
     }
     pub fn sum(&self) -> i64 {
-        self.max.iter().sum()
+        self.max.iter().map(|v| v.0).sum()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::compute;
+    use super::*;
     use std::path::Path;
 
     #[test]
@@ -78,4 +232,66 @@ mod tests {
             206582
         );
     }
+
+    #[test]
+    fn day01_report_example() {
+        let r = report(Path::new("src/inputs/day01_example.txt"), 3).unwrap();
+        assert_eq!(
+            r.top.iter().map(|e| (e.index, e.total)).collect::<Vec<_>>(),
+            vec![(4, 24000), (3, 11000), (5, 10000)]
+        );
+        assert_eq!(r.elves.len(), 5);
+        assert_eq!(r.stats.count, 5);
+        assert_eq!(r.stats.mean, 11000.0);
+        assert_eq!(r.stats.median, 10000.0);
+        assert_eq!(r.stats.empty, 0);
+        assert_eq!(r.percentile(50), Some(10000));
+        assert_eq!(r.percentile(100), Some(24000));
+        assert_eq!(r.percentile(0), None);
+
+        let mut csv = Vec::new();
+        r.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "rank,elf,total,items\n1,4,24000,3\n2,3,11000,2\n3,5,10000,1\n"
+        );
+        let mut table = Vec::new();
+        r.write_table(&mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert!(table.contains("     1      4      24000      3"));
+        assert!(table.contains("median        10000.0"));
+    }
+
+    #[test]
+    fn day01_report_ties() {
+        let elf = |index, total, items| Elf {
+            index,
+            total,
+            items,
+        };
+        let r = Report::new(
+            vec![elf(1, 5, 1), elf(2, 7, 2), elf(3, 0, 0), elf(4, 7, 1)],
+            2,
+        );
+        assert_eq!(r.top, vec![elf(2, 7, 2), elf(4, 7, 1)]);
+        assert_eq!(r.stats.median, 6.0);
+        assert_eq!(r.stats.empty, 1);
+        let r = report(Path::new("src/inputs/day01_input.txt"), 3).unwrap();
+        assert_eq!(r.top.iter().map(|e| e.total).sum::<i64>(), 206582);
+    }
+
+    #[test]
+    fn day01_streaming() {
+        let input = "1000\r\n2000\r\n\r\n4000\r\n\r\n500\r\n\r\n";
+        assert_eq!(compute_reader(input.as_bytes(), 1).unwrap(), 4000);
+        assert_eq!(compute_reader(input.as_bytes(), 2).unwrap(), 7000);
+        assert_eq!(compute_reader(input.as_bytes(), 5).unwrap(), 7500);
+        assert_eq!(compute_reader(input.as_bytes(), 0).unwrap(), 0);
+        assert_eq!(compute_reader(input.as_bytes(), usize::MAX).unwrap(), 7500);
+        assert_eq!(compute_reader("".as_bytes(), 3).unwrap(), 0);
+        let err = compute_reader("1\n\n2\nx3\n".as_bytes(), 1).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 4: invalid calories \"x3\""));
+    }
 }
@@ -1,6 +1,8 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::sync::OnceLock;
 use std::{error, io};
 
 type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
@@ -27,65 +29,261 @@ where
 }
 
 pub fn decode1(s1: &str, s2: &str) -> Result<i64> {
-    let m = Move::from_str(s2)?;
-    let t = Move::from_str(s1)?;
-    Ok(Move::compute_score(m, t))
+    rps().decode1(s1, s2)
 }
 
 pub fn decode2(s1: &str, s2: &str) -> Result<i64> {
-    let theirs = Move::from_str(s1)?;
-    let res = Outcome::from_str(s2)?;
-    let mine = Move::from_i64(((theirs as i64) + (res as i64) + 2) % 3);
-    Ok(Move::compute_score(mine, theirs))
+    rps().decode2(s1, s2)
 }
 
-#[derive(Copy, Clone)]
-enum Move {
-    Rock,
-    Paper,
-    Scissors,
+fn rps() -> &'static Game {
+    static RPS: OnceLock<Game> = OnceLock::new();
+    RPS.get_or_init(Game::rps)
 }
 
-impl Move {
-    fn compute_score(mine: Move, theirs: Move) -> i64 {
-        let m = mine as i64;
-        let t = theirs as i64;
-        3 * ((m - t + 4) % 3) + m + 1
-    }
-    fn from_i64(i: i64) -> Move {
-        match i {
-            0 => Move::Rock,
-            1 => Move::Paper,
-            2 => Move::Scissors,
-            _ => panic!("invalid move: {}", i),
+// A cyclic game with an odd number of moves, where move i beats move j if
+// (i - j) mod n is odd. Every move then beats exactly (n - 1) / 2 others.
+#[derive(Clone, Debug)]
+pub struct Game {
+    names: Vec<String>,
+    move_scores: Vec<i64>,
+    outcome_scores: [i64; 3],
+    theirs: HashMap<String, usize>,
+    mine: HashMap<String, usize>,
+    outcomes: HashMap<String, Outcome>,
+}
+
+impl Game {
+    pub fn new(names: &[&str], move_scores: &[i64], outcome_scores: [i64; 3]) -> Result<Game> {
+        if names.len() < 3 || names.len().is_multiple_of(2) {
+            return Err(format!(
+                "a cyclic game needs an odd number of at least 3 moves: {}",
+                names.len()
+            )
+            .into());
+        }
+        if move_scores.len() != names.len() {
+            return Err(format!(
+                "expected {} move scores, got {}",
+                names.len(),
+                move_scores.len()
+            )
+            .into());
+        }
+        Ok(Game {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            move_scores: move_scores.to_vec(),
+            outcome_scores,
+            theirs: HashMap::new(),
+            mine: HashMap::new(),
+            outcomes: HashMap::new(),
+        })
+    }
+
+    // Maps the symbols in the opponent's and in our column to moves, in order.
+    pub fn with_symbols(mut self, theirs: &[&str], mine: &[&str]) -> Result<Game> {
+        for symbols in [theirs, mine] {
+            if symbols.len() != self.len() {
+                return Err(
+                    format!("expected {} symbols, got {}", self.len(), symbols.len()).into(),
+                );
+            }
+            let unique = symbols.iter().collect::<HashSet<_>>();
+            if unique.len() != symbols.len() {
+                return Err(format!("duplicate symbols: {:?}", symbols).into());
+            }
         }
+        self.theirs = theirs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.to_string(), i))
+            .collect();
+        self.mine = mine
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.to_string(), i))
+            .collect();
+        Ok(self)
+    }
+
+    // Maps symbols to the outcomes lose, draw and win, in order.
+    pub fn with_outcome_symbols(mut self, symbols: [&str; 3]) -> Game {
+        self.outcomes = symbols
+            .iter()
+            .zip([Outcome::Lose, Outcome::Draw, Outcome::Win])
+            .map(|(s, o)| (s.to_string(), o))
+            .collect();
+        self
+    }
+
+    pub fn rps() -> Game {
+        Game::new(&["Rock", "Paper", "Scissors"], &[1, 2, 3], [0, 3, 6])
+            .and_then(|g| g.with_symbols(&["A", "B", "C"], &["X", "Y", "Z"]))
+            .map(|g| g.with_outcome_symbols(["X", "Y", "Z"]))
+            .expect("valid game")
+    }
+
+    pub fn rpsls() -> Game {
+        Game::new(
+            &["Rock", "Paper", "Scissors", "Spock", "Lizard"],
+            &[1, 2, 3, 4, 5],
+            [0, 3, 6],
+        )
+        .and_then(|g| g.with_symbols(&["A", "B", "C", "D", "E"], &["V", "W", "X", "Y", "Z"]))
+        .map(|g| g.with_outcome_symbols(["X", "Y", "Z"]))
+        .expect("valid game")
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
     }
 
-    fn from_str(s: &str) -> Result<Move> {
-        match s {
-            "A" | "X" => Ok(Move::Rock),
-            "B" | "Y" => Ok(Move::Paper),
-            "C" | "Z" => Ok(Move::Scissors),
-            _ => Err(format!("invalid move: {}", s).into()),
+    pub fn name(&self, m: usize) -> &str {
+        &self.names[m]
+    }
+
+    pub fn outcome(&self, mine: usize, theirs: usize) -> Outcome {
+        let d = (mine + self.len() - theirs) % self.len();
+        match d {
+            0 => Outcome::Draw,
+            _ if d % 2 == 1 => Outcome::Win,
+            _ => Outcome::Lose,
         }
     }
+
+    pub fn score(&self, mine: usize, theirs: usize) -> i64 {
+        self.move_scores[mine] + self.outcome_scores[self.outcome(mine, theirs) as usize]
+    }
+
+    // The move that reaches the outcome against theirs. When several do, the
+    // one that scores most is chosen, and the first of those on a tie.
+    pub fn respond(&self, theirs: usize, outcome: Outcome) -> Result<usize> {
+        (0..self.len())
+            .filter(|m| self.outcome(*m, theirs) == outcome)
+            .fold(None, |best: Option<usize>, m| match best {
+                Some(b) if self.move_scores[b] >= self.move_scores[m] => Some(b),
+                _ => Some(m),
+            })
+            .ok_or_else(|| format!("no move reaches {:?}", outcome).into())
+    }
+
+    pub fn decode1(&self, s1: &str, s2: &str) -> Result<i64> {
+        let theirs = self.their_move(s1)?;
+        let mine = *self
+            .mine
+            .get(s2)
+            .ok_or_else(|| format!("invalid move: {}", s2))?;
+        Ok(self.score(mine, theirs))
+    }
+
+    pub fn decode2(&self, s1: &str, s2: &str) -> Result<i64> {
+        let theirs = self.their_move(s1)?;
+        let res = *self
+            .outcomes
+            .get(s2)
+            .ok_or_else(|| format!("invalid outcome: {}", s2))?;
+        Ok(self.score(self.respond(theirs, res)?, theirs))
+    }
+
+    fn their_move(&self, s: &str) -> Result<usize> {
+        Ok(*self
+            .theirs
+            .get(s)
+            .ok_or_else(|| format!("invalid move: {}", s))?)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+// All scores are totals over the rounds of the guide.
+pub struct Analysis {
+    pub rounds: usize,
+    // the share of rounds in which the opponent played each move
+    pub distribution: Vec<f64>,
+    // the single move that scores most when played in every round
+    pub best_response: usize,
+    pub best_response_score: i64,
+    // Against a known distribution the expected score is linear in our
+    // strategy, so the optimal mix only uses best responses. Ties are split
+    // evenly.
+    pub mixed: Vec<f64>,
+    // the expected score of the mixed strategy
+    pub mixed_score: f64,
+    pub guide_score: i64,
+}
+
+// How the second column of the guide is read: as our move, as in part one, or
+// as the outcome to reach, as in part two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Guide {
+    Moves,
+    Outcomes,
+}
+
+// Compares following the guide against the best play given how often the
+// opponent chose each move.
+pub fn analyze(p: &Path, game: &Game, guide: Guide) -> Result<Analysis> {
+    let mut counts = vec![0; game.len()];
+    let mut guide_score = 0;
+    for line in BufReader::new(File::open(p)?).lines() {
+        let s = line?;
+        let (a, b) = s
+            .split_once(' ')
+            .ok_or_else(|| format!("invalid string: {}", s))?;
+        counts[game.their_move(a)?] += 1;
+        guide_score += match guide {
+            Guide::Moves => game.decode1(a, b)?,
+            Guide::Outcomes => game.decode2(a, b)?,
+        };
+    }
+    let rounds = counts.iter().sum::<usize>();
+    let totals = (0..game.len())
+        .map(|m| {
+            (0..game.len())
+                .map(|t| counts[t] as i64 * game.score(m, t))
+                .sum::<i64>()
+        })
+        .collect::<Vec<_>>();
+    let best_response_score = totals.iter().copied().max().unwrap_or(0);
+    let best_response = totals
+        .iter()
+        .position(|t| *t == best_response_score)
+        .unwrap_or(0);
+    let ties = totals.iter().filter(|t| **t == best_response_score).count();
+    let mixed = totals
+        .iter()
+        .map(|t| match *t == best_response_score {
+            true => 1.0 / ties as f64,
+            false => 0.0,
+        })
+        .collect::<Vec<_>>();
+    let mixed_score = mixed.iter().zip(&totals).map(|(m, t)| m * *t as f64).sum();
+    Ok(Analysis {
+        rounds,
+        distribution: counts
+            .iter()
+            .map(|c| match rounds {
+                0 => 0.0,
+                _ => *c as f64 / rounds as f64,
+            })
+            .collect(),
+        best_response,
+        best_response_score,
+        mixed,
+        mixed_score,
+        guide_score,
+    })
 }
 
-enum Outcome {
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
     Lose,
     Draw,
     Win,
 }
-impl Outcome {
-    fn from_str(s: &str) -> Result<Outcome> {
-        match s {
-            "X" => Ok(Outcome::Lose),
-            "Y" => Ok(Outcome::Draw),
-            "Z" => Ok(Outcome::Win),
-            _ => Err(format!("invalid outcome: {}", s).into()),
-        }
-    }
-}
 
 #[cfg(test)]
 mod tests {
@@ -120,4 +318,79 @@ mod tests {
             10116
         );
     }
+
+    #[test]
+    fn day02_rpsls() {
+        let g = Game::rpsls();
+        for m in 0..g.len() {
+            let wins = (0..g.len()).filter(|t| g.outcome(m, *t) == Outcome::Win);
+            assert_eq!(wins.count(), 2);
+        }
+        let id = |n| (0..g.len()).find(|m| g.name(*m) == n).unwrap();
+        for (a, b) in [
+            ("Scissors", "Paper"),
+            ("Paper", "Rock"),
+            ("Rock", "Lizard"),
+            ("Lizard", "Spock"),
+            ("Spock", "Scissors"),
+            ("Scissors", "Lizard"),
+            ("Lizard", "Paper"),
+            ("Paper", "Spock"),
+            ("Spock", "Rock"),
+            ("Rock", "Scissors"),
+        ] {
+            assert_eq!(g.outcome(id(a), id(b)), Outcome::Win, "{} vs {}", a, b);
+            assert_eq!(g.outcome(id(b), id(a)), Outcome::Lose, "{} vs {}", b, a);
+        }
+        assert_eq!(g.decode1("D", "V").unwrap(), 1);
+        assert_eq!(g.decode1("A", "Y").unwrap(), 10);
+        // Spock and Paper both beat Rock, Spock scores more
+        assert_eq!(g.decode2("A", "Z").unwrap(), 10);
+        assert!(g.decode1("F", "V").is_err());
+        assert!(Game::new(&["A", "B"], &[1, 2], [0, 3, 6]).is_err());
+        assert!(Game::new(&["A"], &[1, 2], [0, 3, 6]).is_err());
+        assert!(Game::new(&["R"], &[1], [0, 3, 6]).is_err());
+        assert!(Game::rps()
+            .with_symbols(&["A", "B", "A"], &["X", "Y", "Z"])
+            .is_err());
+        assert!(Game::rps()
+            .with_symbols(&["A", "B", "C"], &["X", "Z", "Z"])
+            .is_err());
+        assert_eq!(
+            compute(Path::new("src/inputs/day02_input.txt"), |a, b| Game::rps()
+                .decode2(a, b))
+            .unwrap(),
+            10116
+        );
+    }
+
+    #[test]
+    fn day02_analyze() {
+        let g = Game::rps();
+        let a = analyze(Path::new("src/inputs/day02_example.txt"), &g, Guide::Moves).unwrap();
+        assert_eq!(a.rounds, 3);
+        assert_eq!(a.distribution, vec![1.0 / 3.0; 3]);
+        assert_eq!(g.name(a.best_response), "Scissors");
+        assert_eq!(a.best_response_score, 18);
+        assert_eq!(a.mixed, vec![0.0, 0.0, 1.0]);
+        assert_eq!(a.mixed_score, 18.0);
+        assert_eq!(a.guide_score, 15);
+        let a = analyze(
+            Path::new("src/inputs/day02_example.txt"),
+            &g,
+            Guide::Outcomes,
+        )
+        .unwrap();
+        assert_eq!(a.guide_score, 12);
+
+        let a = analyze(Path::new("src/inputs/day02_input.txt"), &g, Guide::Moves).unwrap();
+        assert_eq!(a.guide_score, 8392);
+        assert_eq!(a.rounds, 2500);
+        assert_eq!(
+            a.distribution,
+            vec![483.0 / 2500.0, 1076.0 / 2500.0, 941.0 / 2500.0]
+        );
+        assert_eq!((a.best_response, a.best_response_score), (2, 16779));
+        assert_eq!(a.mixed_score, 16779.0);
+    }
 }
@@ -1,53 +1,271 @@
 use std::{
-    collections::HashSet,
+    fmt,
     fs::File,
     io::{BufRead, BufReader},
     path::Path,
+    str::FromStr,
 };
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn compute1(p: &Path) -> Result<i64> {
-    let f = File::open(p)?;
-    let lines = BufReader::new(f).lines();
-    let mut res = 0;
-    for line in lines {
-        let s = line?;
-        let (s1, s2) = s.split_at(s.len() / 2);
-        let a: HashSet<_> = s1.chars().collect();
-        let b: HashSet<_> = s2.chars().collect();
-        res += a.intersection(&b).map(score).sum::<i64>()
-    }
-    Ok(res)
+    compute1_with(p, 2)
 }
 
 pub fn compute2(p: &Path) -> Result<i64> {
-    let mut lines = File::open(p).map(BufReader::new).map(BufRead::lines)?;
-    let mut res = 0;
-    while let Some(line) = lines.next() {
-        if let (Some(s1), Some(s2)) = (lines.next(), lines.next()) {
-            let dupes: HashSet<_> = line?.chars().collect();
-            let h1: HashSet<_> = s1?.chars().collect();
-            let h2: HashSet<_> = s2?.chars().collect();
-
-            res += dupes
-                .iter()
-                .filter(|c| h1.contains(c) && h2.contains(c))
-                .map(score)
-                .sum::<i64>()
+    group_badges(p, 3)
+}
+
+// Sums the priorities of the items found in all m compartments of each
+// rucksack.
+pub fn compute1_with(p: &Path, m: usize) -> Result<i64> {
+    load(p)?
+        .iter()
+        .map(|s| compartments(s, m).map(|cs| common(&cs).score()))
+        .sum()
+}
+
+// Sums the priorities of the items shared by each group of k rucksacks.
+pub fn group_badges(p: &Path, k: usize) -> Result<i64> {
+    if k == 0 {
+        return Err("invalid group size: 0".into());
+    }
+    let lines = load(p)?;
+    if !lines.len().is_multiple_of(k) {
+        return Err(format!(
+            "invalid input, {} lines do not form groups of {}",
+            lines.len(),
+            k
+        )
+        .into());
+    }
+    lines
+        .chunks(k)
+        .map(|g| {
+            g.iter()
+                .map(|s| s.parse::<Items>())
+                .collect::<Result<Vec<_>>>()
+                .map(|items| common(&items).score())
+        })
+        .sum()
+}
+
+fn load(p: &Path) -> Result<Vec<String>> {
+    Ok(File::open(p)
+        .map(BufReader::new)
+        .map(BufRead::lines)?
+        .collect::<std::io::Result<_>>()?)
+}
+
+fn compartments(s: &str, m: usize) -> Result<Vec<Items>> {
+    if m == 0 || !s.len().is_multiple_of(m) {
+        return Err(format!("cannot split {:?} into {} compartments", s, m).into());
+    }
+    if s.is_empty() {
+        return Ok(vec![Items::default(); m]);
+    }
+    s.as_bytes()
+        .chunks(s.len() / m)
+        .map(|c| std::str::from_utf8(c)?.parse())
+        .collect()
+}
+
+fn common(items: &[Items]) -> Items {
+    items
+        .iter()
+        .copied()
+        .reduce(|a, b| a.intersection(b))
+        .unwrap_or_default()
+}
+
+// A set of item types, where bit i stands for the item of priority i.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Items(u64);
+
+impl Items {
+    pub fn intersection(self, other: Items) -> Items {
+        Items(self.0 & other.0)
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn priorities(self) -> impl Iterator<Item = i64> {
+        (1..=52).filter(move |i| self.0 & (1 << i) != 0)
+    }
+
+    pub fn score(self) -> i64 {
+        self.priorities().sum()
+    }
+}
+
+impl FromStr for Items {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Items> {
+        s.chars()
+            .try_fold(Items::default(), |items, c| match priority(c) {
+                Some(p) => Ok(Items(items.0 | 1 << p)),
+                None => Err(format!("invalid item: {:?}", c).into()),
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    UnevenLength(usize),
+    InvalidItem(usize, char),
+    NoSharedItem,
+    SharedItems(usize),
+    IncompleteGroup(usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UnevenLength(n) => write!(f, "length {} does not split evenly", n),
+            Problem::InvalidItem(i, c) => write!(f, "invalid item {:?} at offset {}", c, i),
+            Problem::NoSharedItem => write!(f, "no shared item"),
+            Problem::SharedItems(n) => write!(f, "{} shared items", n),
+            Problem::IncompleteGroup(n) => write!(f, "incomplete group of {}", n),
+        }
+    }
+}
+
+// The items found in every compartment of a rucksack, or every rucksack of a
+// group, with their priorities, and what was wrong with them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    // the first line involved, counting from 1
+    pub line: usize,
+    pub shared: Vec<(char, i64)>,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub rucksacks: Vec<Finding>,
+    pub groups: Vec<Finding>,
+}
+
+// Checks every rucksack split into m compartments and every group of k
+// rucksacks. Invalid items are reported and otherwise ignored.
+pub fn validate(p: &Path, m: usize, k: usize) -> Result<Report> {
+    if m == 0 || k == 0 {
+        return Err(format!("invalid compartments {} or group size {}", m, k).into());
+    }
+    let lines = load(p)?;
+    let mut rucksacks = Vec::new();
+    let mut all = Vec::new();
+    for (i, s) in lines.iter().enumerate() {
+        let mut problems = Vec::new();
+        let items = s
+            .char_indices()
+            .filter_map(|(j, c)| match priority(c) {
+                Some(p) => Some((j, p)),
+                None => {
+                    problems.push(Problem::InvalidItem(j, c));
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        all.push(Items(items.iter().fold(0, |acc, (_, p)| acc | 1 << p)));
+        let shared = if s.len().is_multiple_of(m) {
+            let size = (s.len() / m).max(1);
+            let mut cs = vec![Items::default(); m];
+            for (j, p) in &items {
+                cs[j / size].0 |= 1 << p;
+            }
+            Some(common(&cs))
         } else {
-            return Err("invalid input, want more lines".into());
+            problems.push(Problem::UnevenLength(s.len()));
+            None
+        };
+        rucksacks.push(finding(i + 1, shared, problems));
+    }
+    let groups = all
+        .chunks(k)
+        .enumerate()
+        .map(|(i, g)| match g.len() == k {
+            true => finding(i * k + 1, Some(common(g)), Vec::new()),
+            false => finding(i * k + 1, None, vec![Problem::IncompleteGroup(g.len())]),
+        })
+        .collect();
+    Ok(Report { rucksacks, groups })
+}
+
+// Without shared items to look at, as for uneven lines and incomplete
+// groups, only the problems already found are reported.
+fn finding(line: usize, shared: Option<Items>, mut problems: Vec<Problem>) -> Finding {
+    let shared = shared.map(|items| items.priorities().map(|p| (item(p), p)).collect::<Vec<_>>());
+    match shared.as_ref().map(Vec::len) {
+        Some(0) => problems.push(Problem::NoSharedItem),
+        None | Some(1) => (),
+        Some(n) => problems.push(Problem::SharedItems(n)),
+    }
+    let shared = shared.unwrap_or_default();
+    Finding {
+        line,
+        shared,
+        problems,
+    }
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.rucksacks
+            .iter()
+            .chain(&self.groups)
+            .all(|f| f.problems.is_empty())
+    }
+
+    pub fn score1(&self) -> i64 {
+        Report::score(&self.rucksacks)
+    }
+
+    pub fn score2(&self) -> i64 {
+        Report::score(&self.groups)
+    }
+
+    fn score(findings: &[Finding]) -> i64 {
+        findings
+            .iter()
+            .flat_map(|f| &f.shared)
+            .map(|(_, p)| p)
+            .sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, findings) in [("line", &self.rucksacks), ("group at line", &self.groups)] {
+            for finding in findings {
+                for problem in &finding.problems {
+                    writeln!(f, "{} {}: {}", kind, finding.line, problem)?;
+                }
+            }
         }
+        Ok(())
+    }
+}
+
+fn item(p: i64) -> char {
+    match p {
+        1..=26 => (p + 96) as u8 as char,
+        _ => (p + 38) as u8 as char,
     }
-    Ok(res)
 }
 
-fn score(c: &char) -> i64 {
-    let v = *c as i64;
-    match v {
-        65..=90 => v - 38,
-        97..=122 => v - 96,
-        _ => 0,
+fn priority(c: char) -> Option<i64> {
+    match c {
+        'a'..='z' => Some(c as i64 - 96),
+        'A'..='Z' => Some(c as i64 - 38),
+        _ => None,
     }
 }
 
@@ -84,4 +302,85 @@ mod tests {
             2683
         );
     }
+
+    #[test]
+    fn day03_items() {
+        let items = "vJrwpWtwJgWrhcsFMMfFFhFp".parse::<Items>().unwrap();
+        assert_eq!(items.len(), 14);
+        let cs = compartments("vJrwpWtwJgWrhcsFMMfFFhFp", 2).unwrap();
+        assert_eq!(common(&cs).priorities().collect::<Vec<_>>(), vec![16]);
+        let cs = compartments("abcXabcYabcZ", 3).unwrap();
+        assert_eq!(common(&cs).score(), 6);
+        assert!(compartments("abc", 2).is_err());
+        assert_eq!(compartments("", 2).unwrap(), vec![Items::default(); 2]);
+        assert!("ab1".parse::<Items>().is_err());
+        assert_eq!("azAZ".parse::<Items>().unwrap().score(), 1 + 26 + 27 + 52);
+    }
+
+    #[test]
+    fn day03_group_sizes() {
+        let p = Path::new("src/inputs/day03_example.txt");
+        let all = load(p).unwrap();
+        let each = all.iter().map(|s| s.parse::<Items>().unwrap().score());
+        assert_eq!(group_badges(p, 1).unwrap(), each.sum::<i64>());
+        // the six example rucksacks share no item
+        assert_eq!(group_badges(p, 6).unwrap(), 0);
+        assert!(group_badges(p, 4).is_err());
+        assert!(group_badges(p, 0).is_err());
+        assert_eq!(
+            compute1_with(Path::new("src/inputs/day03_input.txt"), 2).unwrap(),
+            7831
+        );
+    }
+
+    #[test]
+    fn day03_validate() {
+        let r = validate(Path::new("src/inputs/day03_example.txt"), 2, 3).unwrap();
+        assert!(r.is_valid());
+        assert_eq!(r.rucksacks[0].shared, vec![('p', 16)]);
+        assert_eq!(r.groups[1].shared, vec![('Z', 52)]);
+        assert_eq!(r.groups[1].line, 4);
+        assert_eq!((r.score1(), r.score2()), (157, 70));
+        assert_eq!(r.to_string(), "");
+        let r = validate(Path::new("src/inputs/day03_input.txt"), 2, 3).unwrap();
+        assert!(r.is_valid());
+        assert_eq!((r.score1(), r.score2()), (7831, 2683));
+    }
+
+    #[test]
+    fn day03_validate_problems() {
+        let path = std::env::temp_dir().join("day03_validate_problems.txt");
+        std::fs::write(&path, "abcab\nab1Xab\nabcd\nabab\nab1Xcd\nabab\n").unwrap();
+        let r = validate(&path, 2, 3).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!r.is_valid());
+        assert_eq!(r.rucksacks[0].problems, vec![Problem::UnevenLength(5)]);
+        assert_eq!(
+            r.rucksacks[1].problems,
+            vec![Problem::InvalidItem(2, '1'), Problem::SharedItems(2)]
+        );
+        assert_eq!(r.rucksacks[1].shared, vec![('a', 1), ('b', 2)]);
+        assert_eq!(r.rucksacks[2].problems, vec![Problem::NoSharedItem]);
+        assert_eq!(r.rucksacks[3].problems, vec![Problem::SharedItems(2)]);
+        assert_eq!(r.score1(), 9);
+        assert_eq!(r.groups[0].shared, vec![('a', 1), ('b', 2)]);
+        assert_eq!(
+            r.rucksacks[4].problems,
+            vec![Problem::InvalidItem(2, '1'), Problem::NoSharedItem]
+        );
+        assert_eq!(r.groups[1].shared, vec![('a', 1), ('b', 2)]);
+        assert_eq!(
+            r.to_string(),
+            "line 1: length 5 does not split evenly\n\
+             line 2: invalid item '1' at offset 2\n\
+             line 2: 2 shared items\n\
+             line 3: no shared item\n\
+             line 4: 2 shared items\n\
+             line 5: invalid item '1' at offset 2\n\
+             line 5: no shared item\n\
+             line 6: 2 shared items\n\
+             group at line 1: 2 shared items\n\
+             group at line 4: 2 shared items\n"
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::cmp::{max, min};
+use crate::intervals::IntervalSet;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::ops::RangeInclusive;
@@ -8,7 +8,7 @@ type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn compute<F>(p: &Path, pred: F) -> Result<usize>
 where
-    F: Fn(&(RangeInclusive<i64>, RangeInclusive<i64>)) -> bool,
+    F: Fn(&(RangeInclusive<isize>, RangeInclusive<isize>)) -> bool,
 {
     File::open(p)
         .map_err(io::Error::into)
@@ -25,7 +25,7 @@ where
         })
 }
 
-fn decode(s: &str) -> Result<(RangeInclusive<i64>, RangeInclusive<i64>)> {
+fn decode(s: &str) -> Result<(RangeInclusive<isize>, RangeInclusive<isize>)> {
     s.split_once(',')
         .ok_or_else(|| format!("invalid line: {}", s).into())
         .and_then(|(a, b)| {
@@ -35,23 +35,140 @@ fn decode(s: &str) -> Result<(RangeInclusive<i64>, RangeInclusive<i64>)> {
         })
 }
 
-fn decode_range(s: &str) -> Result<RangeInclusive<i64>> {
+fn decode_range(s: &str) -> Result<RangeInclusive<isize>> {
     s.split_once('-')
         .ok_or_else(|| format!("invalid range: {}", s).into())
         .and_then(|(a, b)| {
-            let x = a.parse::<i64>()?;
-            let y = b.parse::<i64>()?;
+            let x = a.parse::<isize>()?;
+            let y = b.parse::<isize>()?;
             Ok(x..=y)
         })
 }
 
-pub fn fully_contained(r: &(RangeInclusive<i64>, RangeInclusive<i64>)) -> bool {
-    return r.0.start() <= r.1.start() && r.0.end() >= r.1.end()
-        || r.1.start() <= r.0.start() && r.1.end() >= r.0.end();
+pub fn fully_contained(r: &(RangeInclusive<isize>, RangeInclusive<isize>)) -> bool {
+    let (a, b) = (
+        IntervalSet::from(r.0.clone()),
+        IntervalSet::from(r.1.clone()),
+    );
+    a.difference(&b).is_empty() || b.difference(&a).is_empty()
 }
 
-pub fn overlap(r: &(RangeInclusive<i64>, RangeInclusive<i64>)) -> bool {
-    return max(r.0.start(), r.1.start()) <= min(r.0.end(), r.1.end());
+pub fn overlap(r: &(RangeInclusive<isize>, RangeInclusive<isize>)) -> bool {
+    let (a, b) = (
+        IntervalSet::from(r.0.clone()),
+        IntervalSet::from(r.1.clone()),
+    );
+    !a.intersection(&b).is_empty()
+}
+
+// One elf's sections, with the line it was assigned on, counting from 1, and
+// whether it is the first or second elf of the pair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assignment {
+    pub line: usize,
+    pub elf: usize,
+    pub sections: RangeInclusive<isize>,
+}
+
+// All assignments sorted by their sections, to answer questions across pairs
+// with a single sweep.
+#[derive(Clone, Debug)]
+pub struct Index {
+    assignments: Vec<Assignment>,
+}
+
+pub fn index(p: &Path) -> Result<Index> {
+    let mut assignments = Vec::new();
+    for (i, line) in BufReader::new(File::open(p)?).lines().enumerate() {
+        let (a, b) = decode(&line?)?;
+        for (elf, sections) in [a, b].into_iter().enumerate() {
+            assignments.push(Assignment {
+                line: i + 1,
+                elf,
+                sections,
+            });
+        }
+    }
+    Ok(Index::new(assignments))
+}
+
+impl Index {
+    pub fn new(mut assignments: Vec<Assignment>) -> Index {
+        assignments.sort_by_key(|a| (*a.sections.start(), *a.sections.end(), a.line, a.elf));
+        Index { assignments }
+    }
+
+    pub fn assignments(&self) -> &[Assignment] {
+        &self.assignments
+    }
+
+    // The assignments that share a section with any other. An assignment
+    // overlaps a later one exactly if it overlaps its successor, and an
+    // earlier one exactly if the earlier one reaching furthest gets to it.
+    pub fn overlapping(&self) -> Vec<&Assignment> {
+        let v = self
+            .assignments
+            .iter()
+            .filter(|a| !a.sections.is_empty())
+            .collect::<Vec<_>>();
+        let mut hit = vec![false; v.len()];
+        let mut furthest: Option<usize> = None;
+        for i in 0..v.len() {
+            if let Some(j) = furthest.filter(|j| v[*j].sections.end() >= v[i].sections.start()) {
+                (hit[i], hit[j]) = (true, true);
+            }
+            if v.get(i + 1)
+                .is_some_and(|n| n.sections.start() <= v[i].sections.end())
+            {
+                (hit[i], hit[i + 1]) = (true, true);
+            }
+            if furthest.is_none_or(|j| v[j].sections.end() < v[i].sections.end()) {
+                furthest = Some(i);
+            }
+        }
+        let mut res = v
+            .into_iter()
+            .zip(hit)
+            .filter_map(|(a, h)| h.then_some(a))
+            .collect::<Vec<_>>();
+        res.sort_by_key(|a| (a.line, a.elf));
+        res
+    }
+
+    // The largest number of elves assigned to one section, and the first
+    // section where that happens.
+    pub fn max_depth(&self) -> (usize, Option<isize>) {
+        let mut events = self
+            .assignments
+            .iter()
+            .filter(|a| !a.sections.is_empty())
+            .flat_map(|a| [(*a.sections.start(), false), (*a.sections.end(), true)])
+            .collect::<Vec<(isize, bool)>>();
+        // ends are inclusive, so at the same section elves arrive before
+        // others leave
+        events.sort();
+        let (mut depth, mut best) = (0, (0, None));
+        for (x, leave) in events {
+            if leave {
+                depth -= 1;
+            } else {
+                depth += 1;
+                if depth > best.0 {
+                    best = (depth, Some(x));
+                }
+            }
+        }
+        best
+    }
+
+    // The sections within bounds that nobody is assigned to.
+    pub fn uncovered(&self, bounds: RangeInclusive<isize>) -> IntervalSet {
+        self.assignments
+            .iter()
+            .map(|a| a.sections.clone())
+            .collect::<IntervalSet>()
+            .complement(bounds)
+    }
 }
 
 #[cfg(test)]
@@ -87,4 +204,68 @@ mod tests {
             843
         );
     }
+
+    #[test]
+    fn day04_index_example() {
+        let idx = index(Path::new("src/inputs/day04_example.txt")).unwrap();
+        assert_eq!(idx.assignments().len(), 12);
+        assert_eq!(idx.overlapping().len(), 12);
+        assert_eq!(idx.max_depth(), (8, Some(6)));
+        assert_eq!(idx.uncovered(0..=10).ranges(), &[0..=1, 10..=10]);
+    }
+
+    #[test]
+    fn day04_index_sweep() {
+        let a = |line, elf, sections| Assignment {
+            line,
+            elf,
+            sections,
+        };
+        let idx = Index::new(vec![
+            a(1, 0, 1..=2),
+            a(1, 1, 10..=20),
+            a(2, 0, 4..=5),
+            a(2, 1, 12..=12),
+            a(3, 0, 19..=25),
+            a(3, 1, 30..=30),
+        ]);
+        assert_eq!(
+            idx.overlapping()
+                .iter()
+                .map(|a| (a.line, a.elf))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (3, 0)]
+        );
+        assert_eq!(idx.max_depth(), (2, Some(12)));
+        assert_eq!(idx.uncovered(1..=30).ranges(), &[3..=3, 6..=9, 26..=29]);
+        assert_eq!(Index::new(Vec::new()).max_depth(), (0, None));
+        let idx = Index::new(vec![
+            a(1, 0, 1..=isize::MAX),
+            a(1, 1, 2..=3),
+            a(2, 0, isize::MAX..=isize::MAX),
+        ]);
+        assert_eq!(idx.max_depth(), (2, Some(2)));
+        assert_eq!(idx.overlapping().len(), 3);
+
+        // compare with all pairs on the input
+        let idx = index(Path::new("src/inputs/day04_input.txt")).unwrap();
+        let v = idx.assignments();
+        let brute = v
+            .iter()
+            .enumerate()
+            .filter(|(i, x)| {
+                v.iter().enumerate().any(|(j, y)| {
+                    *i != j
+                        && x.sections.start() <= y.sections.end()
+                        && y.sections.start() <= x.sections.end()
+                })
+            })
+            .count();
+        assert_eq!(idx.overlapping().len(), brute);
+        let depth = (1..=99)
+            .map(|x| v.iter().filter(|a| a.sections.contains(&x)).count())
+            .max()
+            .unwrap();
+        assert_eq!(idx.max_depth().0, depth);
+    }
 }
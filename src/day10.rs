@@ -1,12 +1,14 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
+use std::str::FromStr;
 use Instruction::*;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn compute1(p: &Path) -> Result<isize> {
-    let mut cpu = CPU::new(load_instructions(p)?);
+    let mut cpu = CPU::new(load_instructions(p)?, Screen::default());
     Ok((20..=220)
         .step_by(40)
         .map(|t| {
@@ -17,9 +19,40 @@ pub fn compute1(p: &Path) -> Result<isize> {
 }
 
 pub fn compute2(p: &Path) -> Result<String> {
-    let mut cpu = CPU::new(load_instructions(p)?);
-    cpu.tick_until(240);
-    Ok(cpu.display)
+    render(p, Screen::default()).map(|f| f.to_string())
+}
+
+pub fn render(p: &Path, screen: Screen) -> Result<Frame> {
+    let mut cpu = CPU::new(load_instructions(p)?, screen);
+    cpu.tick_until(screen.cycles());
+    Ok(cpu.frame)
+}
+
+pub fn frames(p: &Path, screen: Screen) -> Result<Vec<Frame>> {
+    let mut cpu = CPU::new(load_instructions(p)?, screen);
+    Ok((1..=screen.cycles())
+        .map(|t| {
+            cpu.tick_until(t);
+            cpu.frame.clone()
+        })
+        .collect())
+}
+
+pub fn export_pbm(p: &Path, screen: Screen, out: &Path) -> Result<()> {
+    let mut w = BufWriter::new(File::create(out)?);
+    render(p, screen)?.write_pbm(&mut w)?;
+    Ok(w.flush()?)
+}
+
+pub fn export_frames(p: &Path, screen: Screen, dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir)?;
+    let frames = frames(p, screen)?;
+    for (i, frame) in frames.iter().enumerate() {
+        let mut w = BufWriter::new(File::create(dir.join(format!("frame_{:05}.pbm", i + 1)))?);
+        frame.write_pbm(&mut w)?;
+        w.flush()?;
+    }
+    Ok(frames.len())
 }
 
 fn load_instructions(p: &Path) -> Result<Vec<Instruction>> {
@@ -34,8 +67,123 @@ fn load_instructions(p: &Path) -> Result<Vec<Instruction>> {
         })
 }
 
-#[derive(Clone, Copy, Debug)]
-enum Instruction {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    sprite: usize,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize, sprite: usize) -> Result<Screen> {
+        if width == 0 || height == 0 || sprite == 0 {
+            return Err(format!("invalid screen: {}x{}, sprite {}", width, height, sprite).into());
+        }
+        Ok(Screen {
+            width,
+            height,
+            sprite,
+        })
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen {
+            width: 40,
+            height: 6,
+            sprite: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Frame {
+    fn new(screen: &Screen) -> Self {
+        Frame {
+            width: screen.width,
+            height: screen.height,
+            pixels: vec![false; screen.cycles()],
+        }
+    }
+
+    // A zero-width frame, which can only be built by hand, has no rows.
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        let n = if self.width == 0 { 0 } else { self.height };
+        self.pixels.chunks(self.width.max(1)).take(n)
+    }
+
+    pub fn write_pbm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "P1")?;
+        writeln!(w, "{} {}", self.width, self.height)?;
+        for row in self.rows() {
+            let line = row
+                .iter()
+                .map(|&lit| if lit { "1" } else { "0" })
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(w, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in self.rows() {
+            for &lit in row {
+                write!(f, "{}", if lit { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Frame {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let rows = s.lines().collect::<Vec<_>>();
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        if width == 0 {
+            return Err("empty frame".into());
+        }
+        let mut pixels = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                return Err(
+                    format!("row {}: want {} pixels, got {}", i + 1, width, row.len()).into(),
+                );
+            }
+            for c in row.chars() {
+                match c {
+                    '#' => pixels.push(true),
+                    '.' => pixels.push(false),
+                    _ => return Err(format!("row {}: invalid pixel: {}", i + 1, c).into()),
+                }
+            }
+        }
+        Ok(Frame {
+            width,
+            height: rows.len(),
+            pixels,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
     AddX(isize),
     NoOp,
 }
@@ -49,11 +197,12 @@ struct CPU {
     x: isize,
     cur: Option<Instruction>,
 
-    display: String,
+    screen: Screen,
+    frame: Frame,
 }
 
 impl CPU {
-    fn new(p: Vec<Instruction>) -> Self {
+    fn new(p: Vec<Instruction>, screen: Screen) -> Self {
         CPU {
             program: p,
             cycle: 0,
@@ -61,7 +210,8 @@ impl CPU {
             cur: None,
             pc: 0,
             x: 1,
-            display: String::new(),
+            screen,
+            frame: Frame::new(&screen),
         }
     }
 
@@ -82,12 +232,10 @@ impl CPU {
             }
             self.cycle += 1;
 
-            let pos = (self.cycle as isize - 1) % 40;
-            let is_match = (pos - self.x).abs() <= 1;
-            self.display.push(if is_match { '#' } else { '.' });
-            if pos == 39 {
-                self.display.push('\n')
-            }
+            let i = (self.cycle - 1) % self.screen.cycles();
+            let pos = (i % self.screen.width) as isize;
+            let left = self.x - (self.screen.sprite as isize - 1) / 2;
+            self.frame.pixels[i] = (left..left + self.screen.sprite as isize).contains(&pos);
         }
     }
 
@@ -107,6 +255,206 @@ fn parse(s: &str) -> Result<Instruction> {
     }
 }
 
+pub fn run(program: &[Instruction], screen: Screen) -> Frame {
+    let mut cpu = CPU::new(program.to_vec(), screen);
+    cpu.tick_until(screen.cycles());
+    cpu.frame
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub labels: HashMap<String, usize>,
+}
+
+// Assembles addx/noop programs. Besides the two machine instructions, the
+// assembler understands `label:` (bound to the cycle in which the next
+// instruction starts), `; comments` and the macros `wait N`, `set N` and
+// `draw_column N`, which lights the next pixel in column N.
+pub fn assemble(src: &str, screen: Screen) -> Result<Program> {
+    let mut asm = Assembler {
+        screen,
+        program: Program::default(),
+        cycle: 0,
+        x: 1,
+    };
+    for (i, line) in src.lines().enumerate() {
+        asm.line(line)
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+    }
+    Ok(asm.program)
+}
+
+pub fn disassemble(program: &[Instruction]) -> String {
+    program
+        .iter()
+        .map(|i| match i {
+            AddX(dx) => format!("addx {}\n", dx),
+            NoOp => "noop\n".to_string(),
+        })
+        .collect()
+}
+
+struct Assembler {
+    screen: Screen,
+    program: Program,
+    cycle: usize,
+    x: isize,
+}
+
+impl Assembler {
+    fn line(&mut self, line: &str) -> Result<()> {
+        let mut code = line.split(';').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(format!("invalid label: {}", label).into());
+            }
+            if self.program.labels.contains_key(label) {
+                return Err(format!("duplicate label: {}", label).into());
+            }
+            self.program
+                .labels
+                .insert(label.to_string(), self.cycle + 1);
+            code = rest.trim();
+        }
+        match *code.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => (),
+            ["wait", n] => {
+                for _ in 0..n.parse::<usize>()? {
+                    self.emit(NoOp)
+                }
+            }
+            ["set", n] => self.set(n.parse()?),
+            ["draw_column", n] => self.draw_column(n.parse()?)?,
+            _ => self.emit(parse(code)?),
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, i: Instruction) {
+        match i {
+            AddX(dx) => {
+                self.cycle += 2;
+                self.x += dx;
+            }
+            NoOp => self.cycle += 1,
+        }
+        self.program.instructions.push(i);
+    }
+
+    fn set(&mut self, x: isize) {
+        if x != self.x {
+            self.emit(AddX(x - self.x));
+        }
+    }
+
+    fn draw_column(&mut self, col: usize) -> Result<()> {
+        let w = self.screen.width;
+        if col >= w {
+            return Err(format!("column {} out of range 0..{}", col, w).into());
+        }
+        let mut wait = (col + w - self.cycle % w) % w;
+        if self.x != col as isize {
+            if wait < 2 {
+                wait += w;
+            }
+            self.set(col as isize);
+            wait -= 2;
+        }
+        for _ in 0..=wait {
+            self.emit(NoOp);
+        }
+        Ok(())
+    }
+}
+
+// Compiles a target image into a program that draws it. The sprite position
+// is constant during each instruction and only changes after an addx, so
+// every run of constant x must last at least two cycles unless it is the
+// last one. A dynamic program over (cycle, x) finds such a sequence.
+pub fn compile(target: &Frame, screen: Screen) -> Result<Vec<Instruction>> {
+    if target.width != screen.width || target.height != screen.height {
+        return Err(format!(
+            "target is {}x{}, screen is {}x{}",
+            target.width, target.height, screen.width, screen.height
+        )
+        .into());
+    }
+    let w = screen.width as isize;
+    let sprite = screen.sprite as isize;
+    let xs = (-sprite..=w + sprite).collect::<Vec<_>>();
+    let idx = |x: isize| (x + sprite) as usize;
+    let fits = |t: usize, x: isize| {
+        let col = (t % screen.width) as isize;
+        let left = x - (sprite - 1) / 2;
+        (left..left + sprite).contains(&col) == target.pixels[t]
+    };
+
+    // reachable[t][x][long]: x is valid at cycle t, and the current run of
+    // constant x has lasted for at least two cycles if `long`.
+    let n = screen.cycles();
+    let mut prev = vec![vec![[None; 2]; xs.len()]; n];
+    if !fits(0, 1) {
+        return Err("first pixel cannot be drawn with x=1".into());
+    }
+    prev[0][idx(1)][0] = Some((1, 0));
+    for t in 1..n {
+        for &x in &xs {
+            if !fits(t, x) {
+                continue;
+            }
+            // continue the current run
+            for long in 0..2 {
+                if prev[t - 1][idx(x)][long].is_some() {
+                    prev[t][idx(x)][1] = Some((x, long));
+                }
+            }
+            // start a new run after an addx
+            if prev[t][idx(x)][0].is_none() {
+                if let Some(&from) = xs
+                    .iter()
+                    .find(|&&y| y != x && prev[t - 1][idx(y)][1].is_some())
+                {
+                    prev[t][idx(x)][0] = Some((from, 1));
+                }
+            }
+        }
+    }
+
+    let (mut x, mut long) = xs
+        .iter()
+        .flat_map(|&x| (0..2).map(move |l| (x, l)))
+        .find(|&(x, l)| prev[n - 1][idx(x)][l].is_some())
+        .ok_or("target cannot be drawn")?;
+    let mut runs = vec![(x, 0)];
+    for t in (0..n).rev() {
+        runs.last_mut().unwrap().1 += 1;
+        let (px, pl) = prev[t][idx(x)][long].unwrap();
+        if t > 0 && long == 0 {
+            runs.push((px, 0));
+        }
+        x = px;
+        long = pl;
+    }
+    runs.reverse();
+
+    let mut program = Vec::new();
+    for (i, &(x, len)) in runs.iter().enumerate() {
+        match runs.get(i + 1) {
+            Some(&(next, _)) => {
+                program.extend(std::iter::repeat_n(NoOp, len - 2));
+                program.push(AddX(next - x));
+            }
+            None => program.extend(std::iter::repeat_n(NoOp, len)),
+        }
+    }
+    if run(&program, screen) != *target {
+        return Err("compiled program does not reproduce the target".into());
+    }
+    Ok(program)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +502,98 @@ mod tests {
             want
         );
     }
+
+    #[test]
+    fn day10_custom_screen() {
+        let p = Path::new("src/inputs/day10_example.txt");
+        assert_eq!(
+            render(p, Screen::new(40, 6, 3).unwrap())
+                .unwrap()
+                .to_string(),
+            compute2(p).unwrap()
+        );
+        let narrow = render(p, Screen::new(40, 6, 1).unwrap()).unwrap();
+        let wide = render(p, Screen::default()).unwrap();
+        assert!(narrow
+            .pixels
+            .iter()
+            .zip(&wide.pixels)
+            .all(|(n, w)| !n || *w));
+        let tall = render(p, Screen::new(20, 3, 3).unwrap()).unwrap();
+        assert_eq!(tall.to_string().lines().count(), 3);
+        assert!(tall.to_string().lines().all(|l| l.len() == 20));
+        assert!(Screen::new(0, 6, 3).is_err());
+    }
+
+    #[test]
+    fn day10_pbm_and_frames() {
+        let p = Path::new("src/inputs/day10_example.txt");
+        let mut buf = Vec::new();
+        render(p, Screen::default())
+            .unwrap()
+            .write_pbm(&mut buf)
+            .unwrap();
+        let pbm = String::from_utf8(buf).unwrap();
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("40 6"));
+        assert_eq!(lines.next().unwrap(), "1 1 0 0 ".repeat(10).trim_end());
+
+        let frames = frames(p, Screen::default()).unwrap();
+        assert_eq!(frames.len(), 240);
+        assert_eq!(frames[0].pixels.iter().filter(|&&b| b).count(), 1);
+        assert_eq!(frames[239], render(p, Screen::default()).unwrap());
+    }
+
+    #[test]
+    fn day10_assemble() {
+        let src = "\
+        ; light column 10 and 30 of the first row
+        start: draw_column 10
+        draw_column 30 ; same row
+        mid:
+        set -5
+        wait 3
+        addx 2
+        noop
+        ";
+        let program = assemble(src, Screen::default()).unwrap();
+        assert_eq!(program.labels["start"], 1);
+        assert_eq!(program.labels["mid"], 32);
+        let frame = run(&program.instructions, Screen::default());
+        assert!(frame.pixels[10] && frame.pixels[30]);
+        assert!(!frame.pixels[20]);
+        assert_eq!(
+            assemble(&disassemble(&program.instructions), Screen::default())
+                .unwrap()
+                .instructions,
+            program.instructions
+        );
+        assert!(assemble("draw_column 40", Screen::default()).is_err());
+        assert!(assemble("a:\na: noop", Screen::default()).is_err());
+        assert!(assemble("jmp 3", Screen::default()).is_err());
+    }
+
+    #[test]
+    fn day10_compile() {
+        for input in ["src/inputs/day10_example.txt", "src/inputs/day10_input.txt"] {
+            let target: Frame = compute2(Path::new(input)).unwrap().parse().unwrap();
+            let program = compile(&target, Screen::default()).unwrap();
+            assert_eq!(run(&program, Screen::default()), target);
+        }
+        let dark = Frame::new(&Screen::default());
+        assert!(compile(&dark, Screen::default()).is_err());
+
+        assert!("".parse::<Frame>().is_err());
+        assert!("\n#.".parse::<Frame>().is_err());
+        let empty = Frame {
+            width: 0,
+            height: 3,
+            pixels: vec![true; 2],
+        };
+        assert_eq!(empty.to_string(), "");
+        let mut pbm = Vec::new();
+        empty.write_pbm(&mut pbm).unwrap();
+        assert_eq!(String::from_utf8(pbm).unwrap(), "P1\n0 3\n");
+    }
 }
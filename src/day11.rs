@@ -1,41 +1,303 @@
 use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Lines};
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Lines, Write};
+use std::iter::Peekable;
+use std::num::ParseIntError;
+use std::ops::{Div, Rem};
 use std::path::Path;
+use std::str::FromStr;
+use std::vec::IntoIter;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn compute1(p: &Path) -> Result<usize> {
-    let monkeys = load_monkeys(p)?;
-    Ok(chase(monkeys, 20, |x| x / 3))
+    let monkeys = load_monkeys::<usize>(p)?;
+    chase(monkeys, 20, |x| x / 3)
 }
 
 pub fn compute2(p: &Path) -> Result<usize> {
-    let monkeys = load_monkeys(p)?;
-    let period: usize = monkeys.iter().map(|m| m.divisible_by).product();
-    Ok(chase(monkeys, 10000, |x| x % period))
+    compute2_with::<usize>(p)
 }
 
-fn chase<F>(mut monkeys: Vec<Monkey>, rounds: usize, f: F) -> usize
+// Same as compute2, but with u128 worry levels for inputs where the product
+// of the divisors (or its square) does not fit into a usize.
+pub fn compute2_wide(p: &Path) -> Result<usize> {
+    compute2_with::<u128>(p)
+}
+
+fn compute2_with<W: Worry>(p: &Path) -> Result<usize> {
+    let mut monkeys = load_monkeys::<W>(p)?;
+    let period = period(&monkeys)?;
+    reduce_modulo(&mut monkeys, period);
+    chase(monkeys, 10000, |x| x % period)
+}
+
+fn chase<W, F>(monkeys: Vec<Monkey<W>>, rounds: usize, f: F) -> Result<usize>
 where
-    F: Fn(usize) -> usize,
+    W: Worry,
+    F: Fn(W) -> W,
 {
+    chase_traced(monkeys, rounds, f, None)
+}
+
+fn chase_traced<W, F>(
+    mut monkeys: Vec<Monkey<W>>,
+    rounds: usize,
+    f: F,
+    mut trace: Option<&mut Trace>,
+) -> Result<usize>
+where
+    W: Worry,
+    F: Fn(W) -> W,
+{
+    if let Some(trace) = trace.as_deref_mut() {
+        trace.snapshot(0, &monkeys);
+    }
     let mut inspections = HashMap::new();
-    for _ in 0..rounds {
+    for round in 1..=rounds {
         for i in 0..monkeys.len() {
             *inspections.entry(i).or_insert(0) += monkeys[i]
                 .inspect(&f)
+                .map_err(|fault| fault.at(i, round))?
                 .iter()
+                .inspect(|t| {
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.throws.push(ThrowEvent {
+                            round,
+                            from: i,
+                            to: t.to_monkey,
+                            level: t.item.as_u128(),
+                        })
+                    }
+                })
                 .inspect(|t| monkeys[t.to_monkey].items.push_back(t.item))
                 .count();
         }
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.snapshot(round, &monkeys);
+        }
     }
     let mut res = inspections.values().copied().collect::<Vec<_>>();
     res.sort();
-    res.iter().rev().take(2).product()
+    Ok(res.iter().rev().take(2).product())
+}
+
+pub fn trace(p: &Path, rounds: usize, part2: bool) -> Result<Trace> {
+    let mut monkeys = load_monkeys::<usize>(p)?;
+    let mut trace = Trace::default();
+    if part2 {
+        let period = period(&monkeys)?;
+        reduce_modulo(&mut monkeys, period);
+        chase_traced(monkeys, rounds, |x| x % period, Some(&mut trace))?;
+    } else {
+        chase_traced(monkeys, rounds, |x| x / 3, Some(&mut trace))?;
+    }
+    Ok(trace)
+}
+
+pub fn trace_with<F>(p: &Path, rounds: usize, f: F) -> Result<Trace>
+where
+    F: Fn(usize) -> usize,
+{
+    let mut trace = Trace::default();
+    chase_traced(load_monkeys(p)?, rounds, f, Some(&mut trace))?;
+    Ok(trace)
+}
+
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub throws: Vec<ThrowEvent>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThrowEvent {
+    pub round: usize,
+    pub from: usize,
+    pub to: usize,
+    pub level: u128,
+}
+
+// The queue of every monkey at the end of a round, round 0 being the
+// starting position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub round: usize,
+    pub queues: Vec<Vec<u128>>,
+}
+
+impl Trace {
+    fn snapshot<W: Worry>(&mut self, round: usize, monkeys: &[Monkey<W>]) {
+        self.snapshots.push(Snapshot {
+            round,
+            queues: monkeys
+                .iter()
+                .map(|m| m.items.iter().map(|l| l.as_u128()).collect())
+                .collect(),
+        })
+    }
+
+    pub fn write_throws_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "round,from,to,level")?;
+        for t in &self.throws {
+            writeln!(w, "{},{},{},{}", t.round, t.from, t.to, t.level)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_snapshots_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "round,monkey,items")?;
+        for s in &self.snapshots {
+            for (i, q) in s.queues.iter().enumerate() {
+                writeln!(w, "{},{},{}", s.round, i, join(q, " "))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes throws and snapshots as JSON Lines, in the order they happened.
+    pub fn write_jsonl<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut throws = self.throws.iter().peekable();
+        for s in &self.snapshots {
+            while let Some(t) = throws.next_if(|t| t.round <= s.round) {
+                writeln!(
+                    w,
+                    r#"{{"type":"throw","round":{},"from":{},"to":{},"level":{}}}"#,
+                    t.round, t.from, t.to, t.level
+                )?;
+            }
+            let queues = s
+                .queues
+                .iter()
+                .map(|q| format!("[{}]", join(q, ",")))
+                .collect::<Vec<_>>();
+            writeln!(
+                w,
+                r#"{{"type":"snapshot","round":{},"queues":[{}]}}"#,
+                s.round,
+                queues.join(",")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn join(levels: &[u128], sep: &str) -> String {
+    levels
+        .iter()
+        .map(u128::to_string)
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+// Simulates every item on its own. An item's position and worry level at the
+// start of a round determine the rest of its trajectory, so once a state
+// repeats, the inspection counts of the remaining rounds can be extrapolated.
+pub fn compute2_cycles(p: &Path, rounds: usize) -> Result<u128> {
+    let mut monkeys = load_monkeys::<usize>(p)?;
+    let period = period(&monkeys)?;
+    reduce_modulo(&mut monkeys, period);
+    chase_items(&monkeys, rounds, |x| x % period)
+}
+
+fn chase_items<W, F>(monkeys: &[Monkey<W>], rounds: usize, f: F) -> Result<u128>
+where
+    W: Worry,
+    F: Fn(W) -> W,
+{
+    let mut inspections = vec![0u128; monkeys.len()];
+    for (start, m) in monkeys.iter().enumerate() {
+        for &item in &m.items {
+            let counts = item_inspections(monkeys, start, item, rounds, &f)?;
+            for (total, n) in inspections.iter_mut().zip(counts) {
+                *total = total.checked_add(n).ok_or_else(|| count_overflow(rounds))?;
+            }
+        }
+    }
+    inspections.sort();
+    inspections
+        .iter()
+        .rev()
+        .take(2)
+        .try_fold(1u128, |acc, n| acc.checked_mul(*n))
+        .ok_or_else(|| count_overflow(rounds))
+}
+
+fn count_overflow(rounds: usize) -> Box<dyn Error> {
+    format!("inspection counts overflow after {} rounds", rounds).into()
+}
+
+fn item_inspections<W, F>(
+    monkeys: &[Monkey<W>],
+    mut monkey: usize,
+    mut level: W,
+    rounds: usize,
+    f: F,
+) -> Result<Vec<u128>>
+where
+    W: Worry,
+    F: Fn(W) -> W,
+{
+    // history[r] holds the cumulative counts after r rounds
+    let mut history = vec![vec![0u128; monkeys.len()]];
+    let mut seen: HashMap<(usize, W), usize> = HashMap::new();
+    for round in 0..rounds {
+        if let Some(&first) = seen.get(&(monkey, level)) {
+            let len = round - first;
+            let (cycles, rest) = ((rounds - first) / len, (rounds - first) % len);
+            return (0..monkeys.len())
+                .map(|i| {
+                    let per_cycle = history[first + len][i] - history[first][i];
+                    (cycles as u128)
+                        .checked_mul(per_cycle)
+                        .and_then(|n| n.checked_add(history[first + rest][i]))
+                        .ok_or_else(|| count_overflow(rounds))
+                })
+                .collect();
+        }
+        seen.insert((monkey, level), round);
+        let mut counts = history[round].clone();
+        loop {
+            counts[monkey] += 1;
+            let t = monkeys[monkey]
+                .throw(level, &f)
+                .map_err(|fault| fault.at(monkey, round + 1))?;
+            level = t.item;
+            // items thrown forward are inspected again in the same round
+            let forward = t.to_monkey > monkey;
+            monkey = t.to_monkey;
+            if !forward {
+                break;
+            }
+        }
+        history.push(counts);
+    }
+    Ok(history.pop().unwrap_or_default())
+}
+
+// The product of all divisors, which every level can be reduced modulo
+// without changing where items are thrown.
+fn period<W: Worry>(monkeys: &[Monkey<W>]) -> Result<W> {
+    Ok(monkeys
+        .iter()
+        .try_fold(W::from_usize(1), |acc, m| {
+            acc.checked_mul(W::from_usize(m.divisible_by))
+        })
+        .ok_or("product of divisors overflows, use wide worry levels")?)
 }
 
-fn load_monkeys(p: &Path) -> Result<Vec<Monkey>> {
+// Tells the monkeys that worry levels are only known modulo the period, so
+// that subtraction wraps around instead of dropping below zero.
+fn reduce_modulo<W: Worry>(monkeys: &mut [Monkey<W>], period: W) {
+    for m in monkeys {
+        m.modulus = Some(period);
+    }
+}
+
+fn load_monkeys<W: Worry>(p: &Path) -> Result<Vec<Monkey<W>>> {
     File::open(p)
         .map_err(io::Error::into)
         .map(BufReader::new)
@@ -47,28 +309,257 @@ fn load_monkeys(p: &Path) -> Result<Vec<Monkey>> {
 }
 
 #[derive(Debug)]
-struct Monkey {
-    pub items: VecDeque<usize>,
-    pub operation: Op,
+pub struct Overflow {
+    pub monkey: usize,
+    pub round: usize,
+    pub level: String,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "worry level {} overflowed at monkey {} in round {}",
+            self.level, self.monkey, self.round
+        )
+    }
+}
+
+impl Error for Overflow {}
+
+#[derive(Debug)]
+pub struct Underflow {
+    pub monkey: usize,
+    pub round: usize,
+    pub level: String,
+}
+
+impl fmt::Display for Underflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "worry level {} dropped below zero at monkey {} in round {}",
+            self.level, self.monkey, self.round
+        )
+    }
+}
+
+impl Error for Underflow {}
+
+// The level an operation failed on, and how.
+#[derive(Debug)]
+enum Fault<W> {
+    Overflow(W),
+    Underflow(W),
+}
+
+impl<W: Worry> Fault<W> {
+    fn at(self, monkey: usize, round: usize) -> Box<dyn Error> {
+        match self {
+            Fault::Overflow(level) => Box::new(Overflow {
+                monkey,
+                round,
+                level: level.to_string(),
+            }),
+            Fault::Underflow(level) => Box::new(Underflow {
+                monkey,
+                round,
+                level: level.to_string(),
+            }),
+        }
+    }
+}
+
+trait Worry:
+    Copy
+    + Eq
+    + Hash
+    + fmt::Display
+    + FromStr<Err = ParseIntError>
+    + Rem<Output = Self>
+    + Div<Output = Self>
+{
+    const ZERO: Self;
+    fn from_usize(n: usize) -> Self;
+    fn as_u128(self) -> u128;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_worry {
+    ($($t:ty),*) => {$(
+        impl Worry for $t {
+            const ZERO: Self = 0;
+            fn from_usize(n: usize) -> Self {
+                n as $t
+            }
+            fn as_u128(self) -> u128 {
+                self as u128
+            }
+            fn checked_add(self, other: Self) -> Option<Self> {
+                <$t>::checked_add(self, other)
+            }
+            fn checked_sub(self, other: Self) -> Option<Self> {
+                <$t>::checked_sub(self, other)
+            }
+            fn checked_mul(self, other: Self) -> Option<Self> {
+                <$t>::checked_mul(self, other)
+            }
+        }
+    )*};
+}
+
+impl_worry!(usize, u128);
+
+#[derive(Debug)]
+struct Monkey<W> {
+    pub items: VecDeque<W>,
+    pub operation: Expr,
     pub divisible_by: usize,
     pub if_true: usize,
     pub if_false: usize,
+    pub modulus: Option<W>,
 }
 
-struct Throw {
-    item: usize,
+struct Throw<W> {
+    item: W,
     to_monkey: usize,
 }
 
-#[derive(Debug)]
-enum Op {
-    Add(usize),
-    Mul(usize),
-    Square,
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Old,
+    Const(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // Failures report the level the operation started from.
+    fn eval<W: Worry>(&self, old: W, modulus: Option<W>) -> std::result::Result<W, Fault<W>> {
+        let overflow = Fault::Overflow(old);
+        match self {
+            Expr::Old => Ok(old),
+            Expr::Const(n) => Ok(W::from_usize(*n)),
+            Expr::Add(a, b) => a
+                .eval(old, modulus)?
+                .checked_add(b.eval(old, modulus)?)
+                .ok_or(overflow),
+            Expr::Sub(a, b) => {
+                let (a, b) = (a.eval(old, modulus)?, b.eval(old, modulus)?);
+                match modulus {
+                    // a reduced level may be below b when the real one is not
+                    Some(p) => (a % p)
+                        .checked_add(p)
+                        .and_then(|a| a.checked_sub(b % p))
+                        .map(|d| d % p)
+                        .ok_or(overflow),
+                    None => a.checked_sub(b).ok_or(Fault::Underflow(old)),
+                }
+            }
+            Expr::Mul(a, b) => a
+                .eval(old, modulus)?
+                .checked_mul(b.eval(old, modulus)?)
+                .ok_or(overflow),
+        }
+    }
+
+    fn parse(s: &str) -> Result<Expr> {
+        let mut tokens = tokenize(s)?.into_iter().peekable();
+        let expr = Expr::parse_sum(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected token {:?} in expression: {}", t, s).into()),
+        }
+    }
+
+    fn parse_sum(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr> {
+        let mut lhs = Expr::parse_product(tokens)?;
+        while let Some(op) = tokens.next_if(|t| matches!(t, Token::Plus | Token::Minus)) {
+            let rhs = Expr::parse_product(tokens)?;
+            lhs = match op {
+                Token::Plus => Expr::Add(Box::new(lhs), Box::new(rhs)),
+                _ => Expr::Sub(Box::new(lhs), Box::new(rhs)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_product(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr> {
+        let mut lhs = Expr::parse_operand(tokens)?;
+        while tokens.next_if_eq(&Token::Star).is_some() {
+            let rhs = Expr::parse_operand(tokens)?;
+            lhs = Expr::Mul(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_operand(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr> {
+        match tokens.next() {
+            Some(Token::Old) => Ok(Expr::Old),
+            Some(Token::Num(n)) => Ok(Expr::Const(n)),
+            Some(Token::Open) => {
+                let expr = Expr::parse_sum(tokens)?;
+                match tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    t => Err(format!("expected ')', got {:?}", t).into()),
+                }
+            }
+            t => Err(format!("expected operand, got {:?}", t).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Old,
+    Num(usize),
+    Plus,
+    Minus,
+    Star,
+    Open,
+    Close,
 }
 
-impl Monkey {
-    fn parse(ss: &[String]) -> Result<Monkey> {
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut res = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            ' ' => continue,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '0'..='9' => {
+                let mut n = String::from(c);
+                while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    n.push(d)
+                }
+                Token::Num(n.parse()?)
+            }
+            'a'..='z' => {
+                let mut w = String::from(c);
+                while let Some(d) = chars.next_if(char::is_ascii_lowercase) {
+                    w.push(d)
+                }
+                match w.as_str() {
+                    "old" => Token::Old,
+                    _ => return Err(format!("unknown identifier: {}", w).into()),
+                }
+            }
+            _ => return Err(format!("invalid character: {}", c).into()),
+        };
+        res.push(token);
+    }
+    Ok(res)
+}
+
+impl<W: Worry> Monkey<W> {
+    fn parse(ss: &[String]) -> Result<Monkey<W>> {
         match *ss[0].split_whitespace().collect::<Vec<_>>().as_slice() {
             ["Monkey", _] => (),
             _ => return Err(format!("invalid first line: {}", ss[0]).into()),
@@ -77,22 +568,15 @@ impl Monkey {
             .strip_prefix("  Starting items: ")
             .ok_or(format!("invalid line: {}", ss[1]))?
             .split(", ")
-            .map(str::parse::<usize>)
+            .map(str::parse::<W>)
             .collect::<std::result::Result<VecDeque<_>, _>>()?;
-        let operation = match *ss[2].split_whitespace().collect::<Vec<_>>().as_slice() {
-            ["Operation:", "new", "=", "old", "*", "old"] => Op::Square,
-            ["Operation:", "new", "=", "old", op, operand] => {
-                let n: usize = operand
-                    .parse()
-                    .map_err(|e| format!("invalid third line: {} {}", ss[2], e))?;
-                match op {
-                    "*" => Op::Mul(n),
-                    "+" => Op::Add(n),
-                    _ => return Err(format!("invalid operation: {}", op).into()),
-                }
-            }
-            _ => return Err(format!("invalid third line: {}", ss[2]).into()),
-        };
+        let operation = ss[2]
+            .trim()
+            .strip_prefix("Operation: new =")
+            .ok_or_else(|| format!("invalid third line: {}", ss[2]))
+            .and_then(|e| {
+                Expr::parse(e).map_err(|err| format!("invalid third line: {} {}", ss[2], err))
+            })?;
         let divisible_by: usize = match *ss[3].split_whitespace().collect::<Vec<_>>().as_slice() {
             ["Test:", "divisible", "by", divisor] => divisor.parse()?,
             _ => return Err(format!("invalid fourth line: {}", ss[2]).into()),
@@ -111,30 +595,35 @@ impl Monkey {
             divisible_by,
             if_true,
             if_false,
+            modulus: None,
         })
     }
 
-    fn inspect<F>(&mut self, worry_fn: F) -> Vec<Throw>
+    fn inspect<F>(&mut self, worry_fn: F) -> std::result::Result<Vec<Throw<W>>, Fault<W>>
     where
-        F: Fn(usize) -> usize,
+        F: Fn(W) -> W,
     {
-        self.items
-            .drain(..)
-            .map(|level| match self.operation {
-                Op::Add(x) => level + x,
-                Op::Mul(x) => level * x,
-                Op::Square => level * level,
-            })
-            .map(worry_fn)
-            .map(|level| Throw {
-                item: level,
-                to_monkey: match level % self.divisible_by {
-                    0 => self.if_true,
-                    _ => self.if_false,
-                },
-            })
+        let items = self.items.drain(..).collect::<Vec<_>>();
+        items
+            .into_iter()
+            .map(|level| self.throw(level, &worry_fn))
             .collect()
     }
+
+    fn throw<F>(&self, level: W, worry_fn: F) -> std::result::Result<Throw<W>, Fault<W>>
+    where
+        F: Fn(W) -> W,
+    {
+        let level = worry_fn(self.operation.eval(level, self.modulus)?);
+        Ok(Throw {
+            item: level,
+            to_monkey: if level % W::from_usize(self.divisible_by) == W::ZERO {
+                self.if_true
+            } else {
+                self.if_false
+            },
+        })
+    }
 }
 
 #[cfg(test)]
@@ -172,4 +661,214 @@ mod tests {
             15305381442
         );
     }
+
+    #[test]
+    fn day11_expressions() {
+        let eval = |s: &str, old: usize| Expr::parse(s).unwrap().eval(old, None).unwrap();
+        assert_eq!(eval("old * old", 7), 49);
+        assert_eq!(eval("old + old", 7), 14);
+        assert_eq!(eval("old + 3 * 2", 7), 13);
+        assert_eq!(eval("(old + 3) * 2 - 1", 7), 19);
+        assert_eq!(eval("old - 2 - 3", 7), 2);
+        assert_eq!(eval("((old))", 7), 7);
+        let expr = Expr::parse("old - 10").unwrap();
+        assert!(matches!(expr.eval(3usize, None), Err(Fault::Underflow(3))));
+        assert!(matches!(expr.eval(3usize, Some(7)), Ok(0)));
+        assert!(matches!(expr.eval(12usize, Some(7)), Ok(2)));
+        for invalid in ["old / 2", "(old + 1", "old old", "new + 1", ""] {
+            assert!(Expr::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let monkey = [
+            "Monkey 0:",
+            "  Starting items: 79, 98",
+            "  Operation: new = old + old",
+            "  Test: divisible by 23",
+            "    If true: throw to monkey 2",
+            "    If false: throw to monkey 3",
+        ]
+        .map(String::from);
+        let mut m = Monkey::<usize>::parse(&monkey).unwrap();
+        let levels = m
+            .inspect(|x| x)
+            .unwrap()
+            .iter()
+            .map(|t| t.item)
+            .collect::<Vec<_>>();
+        assert_eq!(levels, vec![158, 196]);
+    }
+
+    fn monkeys<W: Worry>(ops: &[&str], divisors: &[usize]) -> Vec<Monkey<W>> {
+        ops.iter()
+            .zip(divisors)
+            .enumerate()
+            .map(|(i, (op, d))| {
+                let lines = [
+                    format!("Monkey {}:", i),
+                    "  Starting items: 3, 5".to_string(),
+                    format!("  Operation: new = {}", op),
+                    format!("  Test: divisible by {}", d),
+                    format!("    If true: throw to monkey {}", (i + 1) % ops.len()),
+                    format!("    If false: throw to monkey {}", (i + 1) % ops.len()),
+                ];
+                Monkey::parse(&lines).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn day11_overflow() {
+        let ops = ["old * old", "old + 7"];
+        let err = chase(monkeys::<usize>(&ops, &[2, 3]), 10, |x| x).unwrap_err();
+        let overflow = err.downcast_ref::<Overflow>().unwrap();
+        assert_eq!((overflow.monkey, overflow.round), (0, 5));
+        let err = chase(monkeys::<usize>(&["old - 4"], &[2]), 1, |x| x).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "worry level 3 dropped below zero at monkey 0 in round 1"
+        );
+        assert!(err.downcast_ref::<Underflow>().is_some());
+
+        let divisors = [1000003, 1000033, 1000037];
+        let period = divisors.iter().product::<usize>();
+        let ops = ["old * old", "old * 19", "old + 3"];
+        assert!(chase(monkeys::<usize>(&ops, &divisors), 100, |x| x % period).is_err());
+        // every monkey throws to its successor, so the counts do not depend
+        // on the worry levels
+        let period = period as u128;
+        assert_eq!(
+            chase(monkeys::<u128>(&ops, &divisors), 100, |x| x % period).unwrap(),
+            chase(monkeys::<usize>(&ops, &divisors), 100, |x| x % 7).unwrap()
+        );
+    }
+
+    #[test]
+    fn day11_period_overflow() {
+        let path = std::env::temp_dir().join("day11_period_overflow.txt");
+        let input = (0..3)
+            .map(|i| {
+                format!(
+                    "Monkey {}:\n  Starting items: 1\n  Operation: new = old + 1\n  \
+                     Test: divisible by {}\n    If true: throw to monkey 0\n    \
+                     If false: throw to monkey 0\n",
+                    i,
+                    (1usize << 30) + 2 * i + 1
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(&path, input).unwrap();
+        let msg = "product of divisors overflows, use wide worry levels";
+        assert_eq!(compute2(&path).unwrap_err().to_string(), msg);
+        assert_eq!(compute2_cycles(&path, 10).unwrap_err().to_string(), msg);
+        assert_eq!(trace(&path, 10, true).unwrap_err().to_string(), msg);
+        assert!(compute2_wide(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn day11_subtract_modulo() {
+        // the reduced level 0 of 6 must not make old - 1 drop below zero
+        let divisors = [2, 3];
+        let ops = ["old - 1", "old * 3"];
+        let mut reduced = monkeys::<usize>(&ops, &divisors);
+        reduce_modulo(&mut reduced, 6);
+        assert_eq!(
+            chase(reduced, 30, |x| x % 6).unwrap(),
+            chase(monkeys::<u128>(&ops, &divisors), 30, |x| x).unwrap()
+        );
+    }
+
+    #[test]
+    fn day11_part2_wide() {
+        assert_eq!(
+            compute2_wide(Path::new("src/inputs/day11_input.txt")).unwrap(),
+            15305381442
+        );
+    }
+
+    #[test]
+    fn day11_cycles() {
+        for input in ["src/inputs/day11_example.txt", "src/inputs/day11_input.txt"] {
+            let monkeys = load_monkeys::<usize>(Path::new(input)).unwrap();
+            let period: usize = monkeys.iter().map(|m| m.divisible_by).product();
+            for rounds in [1, 2, 20, 137, 1000] {
+                let monkeys = load_monkeys::<usize>(Path::new(input)).unwrap();
+                assert_eq!(
+                    chase_items(&monkeys, rounds, |x| x % period).unwrap(),
+                    chase(monkeys, rounds, |x| x % period).unwrap() as u128,
+                    "{} rounds",
+                    rounds
+                );
+            }
+            assert_eq!(
+                chase_items(&monkeys, 20, |x| x / 3).unwrap(),
+                compute1(Path::new(input)).unwrap() as u128
+            );
+        }
+        assert_eq!(
+            compute2_cycles(Path::new("src/inputs/day11_input.txt"), 10000).unwrap(),
+            15305381442
+        );
+        assert!(
+            compute2_cycles(Path::new("src/inputs/day11_example.txt"), 1_000_000_000_000).is_ok()
+        );
+        assert_eq!(
+            compute2_cycles(Path::new("src/inputs/day11_example.txt"), usize::MAX)
+                .unwrap_err()
+                .to_string(),
+            format!("inspection counts overflow after {} rounds", usize::MAX)
+        );
+    }
+
+    #[test]
+    fn day11_trace() {
+        let p = Path::new("src/inputs/day11_example.txt");
+        let trace = trace(p, 20, false).unwrap();
+        assert_eq!(
+            trace.throws[0],
+            ThrowEvent {
+                round: 1,
+                from: 0,
+                to: 3,
+                level: 500
+            }
+        );
+        assert_eq!(trace.snapshots.len(), 21);
+        assert_eq!(
+            trace.snapshots[1].queues,
+            vec![
+                vec![20, 23, 27, 26],
+                vec![2080, 25, 167, 207, 401, 1046],
+                vec![],
+                vec![]
+            ]
+        );
+
+        let mut csv = Vec::new();
+        trace.write_throws_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().take(2).collect::<Vec<_>>(),
+            vec!["round,from,to,level", "1,0,3,500"]
+        );
+        let mut csv = Vec::new();
+        trace.write_snapshots_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(5), Some("1,0,20 23 27 26"));
+
+        let mut jsonl = Vec::new();
+        trace.write_jsonl(&mut jsonl).unwrap();
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let lines = jsonl.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), trace.throws.len() + trace.snapshots.len());
+        assert_eq!(
+            lines[0],
+            r#"{"type":"snapshot","round":0,"queues":[[79,98],[54,65,75,74],[79,60,97],[74]]}"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"type":"throw","round":1,"from":0,"to":3,"level":500}"#
+        );
+    }
 }
@@ -1,9 +1,17 @@
 use std::cmp::Ordering;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Lines};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::convert::Infallible;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
 use std::iter::Peekable;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::slice;
 use std::str::{Chars, FromStr};
+use std::sync::atomic::{self, AtomicUsize};
+use std::time::{SystemTime, UNIX_EPOCH};
 use Packet::*;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -45,36 +53,88 @@ fn load(p: &Path) -> Result<Vec<Packet>> {
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
-enum Packet {
+pub enum Packet {
     Int(isize),
     List(Vec<Packet>),
 }
 
-impl Packet {
-    fn from_chars(chars: &mut Peekable<Chars>) -> Result<Packet> {
+// Nesting limit for parsed packets, deep enough for any puzzle input while
+// keeping the recursive parser well within the stack.
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn next_if(&mut self, f: impl FnOnce(&char) -> bool) -> Option<char> {
+        let c = self.chars.next_if(f)?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn error<T>(&self, msg: String) -> Result<T> {
+        Err(format!("at offset {}: {}", self.pos.saturating_sub(1), msg).into())
+    }
+
+    fn packet(&mut self) -> Result<Packet> {
+        match self.next() {
+            Some('[') => (),
+            Some(c) => return self.error(format!("expected '[', got '{}'", c)),
+            None => return Err("empty input".into()),
+        }
+        let p = self.list(1)?;
+        match self.next() {
+            None => Ok(p),
+            Some(c) => self.error(format!("unexpected trailing input '{}'", c)),
+        }
+    }
+
+    fn list(&mut self, depth: usize) -> Result<Packet> {
+        if depth > MAX_DEPTH {
+            return self.error(format!("nesting deeper than {}", MAX_DEPTH));
+        }
         let mut vs = Vec::new();
-        while let Some(c) = chars.next() {
-            let pkt = match c {
-                '[' => Packet::from_chars(chars)?,
-                '0'..='9' => {
-                    let mut s = String::from(c);
-                    while let Some(d) = chars.next_if(|ch| ch.is_numeric()) {
-                        s.push(d)
-                    }
-                    Packet::Int(s.parse::<isize>()?)
-                }
-                ']' => break,
-                _ => return Err(format!("expected [ or number, got {}", c).into()),
+        if self.next_if(|&c| c == ']').is_some() {
+            return Ok(Packet::List(vs));
+        }
+        loop {
+            let pkt = match self.next() {
+                Some('[') => self.list(depth + 1)?,
+                Some(c @ ('-' | '0'..='9')) => self.int(c)?,
+                Some(c) => return self.error(format!("expected '[' or number, got '{}'", c)),
+                None => return self.end_of_input(),
             };
             vs.push(pkt);
-            match chars.next() {
-                Some(']') => break,
+            match self.next() {
+                Some(']') => return Ok(Packet::List(vs)),
                 Some(',') => continue,
-                Some(c) => return Err(format!("expected ']' or ',', got '{}'", c).into()),
-                None => return Err("unexpected end of input".into()),
+                Some(c) => return self.error(format!("expected ']' or ',', got '{}'", c)),
+                None => return self.end_of_input(),
             }
         }
-        Ok(Packet::List(vs))
+    }
+
+    fn end_of_input<T>(&self) -> Result<T> {
+        Err(format!("at offset {}: unexpected end of input", self.pos).into())
+    }
+
+    fn int(&mut self, first: char) -> Result<Packet> {
+        let start = self.pos - 1;
+        let mut s = String::from(first);
+        while let Some(d) = self.next_if(char::is_ascii_digit) {
+            s.push(d)
+        }
+        s.parse::<isize>()
+            .map(Packet::Int)
+            .map_err(|e| format!("at offset {}: invalid number '{}': {}", start, s, e).into())
     }
 }
 
@@ -82,23 +142,180 @@ impl FromStr for Packet {
     type Err = String;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let mut chars = s.chars().peekable();
-        if let Some('[') = chars.next() {
-            Packet::from_chars(&mut chars)
-                .map_err(|e| format!("invalid packet: {} error: {}", s, e))
-        } else {
-            Err(format!("invalid packet: {}", s))
+        Parser {
+            chars: s.chars().peekable(),
+            pos: 0,
+        }
+        .packet()
+        .map_err(|e| format!("invalid packet: {} error: {}", s, e))
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Int(n) => write!(f, "{}", n),
+            List(ps) => {
+                write!(f, "[")?;
+                for (i, p) in ps.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", p)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    // integers are kept apart from other numbers so that they stay exact
+    Int(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+impl From<&Packet> for JsonValue {
+    fn from(p: &Packet) -> Self {
+        match p {
+            Int(n) => JsonValue::Int(*n as i64),
+            List(ps) => JsonValue::Array(ps.iter().map(JsonValue::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&JsonValue> for Packet {
+    type Error = String;
+
+    fn try_from(v: &JsonValue) -> std::result::Result<Self, Self::Error> {
+        match v {
+            JsonValue::Int(n) => isize::try_from(*n)
+                .map(Int)
+                .map_err(|_| format!("integer out of range: {}", n)),
+            // isize::MAX as f64 rounds up to 2^63, which is out of range
+            JsonValue::Number(n)
+                if n.fract() == 0.0 && *n >= isize::MIN as f64 && *n < isize::MAX as f64 =>
+            {
+                Ok(Int(*n as isize))
+            }
+            JsonValue::Array(vs) => vs
+                .iter()
+                .map(Packet::try_from)
+                .collect::<std::result::Result<_, _>>()
+                .map(List),
+            _ => Err(format!("not a packet value: {:?}", v)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// A step on the way to the deciding comparison: either descending into the
+// i-th element of both lists, or promoting an integer on one side to a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Index(usize),
+    Promote(Side),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Ints(isize, isize),
+    Lengths(usize, usize),
+    Equal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub ordering: Ordering,
+    pub path: Vec<Step>,
+    pub rule: Rule,
+}
+
+impl Packet {
+    pub fn explain_cmp(&self, other: &Packet) -> Explanation {
+        let mut path = Vec::new();
+        let rule = explain(self, other, &mut path);
+        let ordering = match rule {
+            Rule::Ints(a, b) => a.cmp(&b),
+            Rule::Lengths(a, b) => a.cmp(&b),
+            Rule::Equal => Ordering::Equal,
+        };
+        Explanation {
+            ordering,
+            path,
+            rule,
+        }
+    }
+}
+
+fn explain(left: &Packet, right: &Packet, path: &mut Vec<Step>) -> Rule {
+    let promoted = |side, l: &Packet, r: &Packet, path: &mut Vec<Step>| {
+        path.push(Step::Promote(side));
+        let rule = explain(l, r, path);
+        if rule == Rule::Equal {
+            path.pop();
+        }
+        rule
+    };
+    match (left, right) {
+        (Int(a), Int(b)) if a == b => Rule::Equal,
+        (Int(a), Int(b)) => Rule::Ints(*a, *b),
+        (List(a), List(b)) => {
+            for (i, (l, r)) in a.iter().zip(b).enumerate() {
+                path.push(Step::Index(i));
+                match explain(l, r, path) {
+                    Rule::Equal => path.pop(),
+                    rule => return rule,
+                };
+            }
+            if a.len() == b.len() {
+                Rule::Equal
+            } else {
+                Rule::Lengths(a.len(), b.len())
+            }
+        }
+        (Int(a), List(_)) => promoted(Side::Left, &List(vec![Int(*a)]), right, path),
+        (List(_), Int(b)) => promoted(Side::Right, left, &List(vec![Int(*b)]), path),
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.path {
+            match step {
+                Step::Index(i) => write!(f, "compare element {}; ", i)?,
+                Step::Promote(Side::Left) => write!(f, "mixed types, convert left to list; ")?,
+                Step::Promote(Side::Right) => write!(f, "mixed types, convert right to list; ")?,
+            }
+        }
+        match self.rule {
+            Rule::Ints(a, b) if a < b => write!(f, "{} < {}, right order", a, b),
+            Rule::Ints(a, b) => write!(f, "{} > {}, wrong order", a, b),
+            Rule::Lengths(a, b) if a < b => {
+                write!(f, "left ran out of items ({} < {}), right order", a, b)
+            }
+            Rule::Lengths(a, b) => write!(f, "right ran out of items ({} > {}), wrong order", a, b),
+            Rule::Equal => write!(f, "packets are equal"),
         }
     }
 }
 
 impl Ord for Packet {
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Int(a), Int(b)) => a.cmp(b),
-            (List(a), List(b)) => a.cmp(b),
-            (Int(a), List(b)) => vec![Int(*a)].cmp(b),
-            (List(a), Int(b)) => a.cmp(&vec![Int(*b)]),
+        match cmp_tokens(PacketTokens::new(self), PacketTokens::new(other)) {
+            Ok(ordering) => ordering,
+            Err(e) => match e {},
         }
     }
 }
@@ -109,6 +326,509 @@ impl PartialOrd for Packet {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Int(isize),
+}
+
+// Compares two packets given as token streams, without recursion. When an
+// integer meets a list, the integer is pushed back together with a virtual
+// closing bracket, which is the token stream of the promoted list.
+fn cmp_tokens<L, R, E>(left: L, right: R) -> std::result::Result<Ordering, E>
+where
+    L: Iterator<Item = std::result::Result<Token, E>>,
+    R: Iterator<Item = std::result::Result<Token, E>>,
+{
+    let mut left = Promotable::new(left);
+    let mut right = Promotable::new(right);
+    loop {
+        match (left.next().transpose()?, right.next().transpose()?) {
+            (None, None) => return Ok(Ordering::Equal),
+            (Some(Token::Open), Some(Token::Open)) => (),
+            (Some(Token::Close), Some(Token::Close)) => (),
+            (None, _) | (Some(Token::Close), _) => return Ok(Ordering::Less),
+            (_, None) | (_, Some(Token::Close)) => return Ok(Ordering::Greater),
+            (Some(Token::Int(a)), Some(Token::Int(b))) if a == b => (),
+            (Some(Token::Int(a)), Some(Token::Int(b))) => return Ok(a.cmp(&b)),
+            (Some(Token::Int(a)), Some(Token::Open)) => left.promote(a),
+            (Some(Token::Open), Some(Token::Int(b))) => right.promote(b),
+        }
+    }
+}
+
+struct Promotable<I> {
+    tokens: I,
+    int: Option<isize>,
+    closes: usize,
+}
+
+impl<I, E> Promotable<I>
+where
+    I: Iterator<Item = std::result::Result<Token, E>>,
+{
+    fn new(tokens: I) -> Self {
+        Promotable {
+            tokens,
+            int: None,
+            closes: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<std::result::Result<Token, E>> {
+        if let Some(n) = self.int.take() {
+            return Some(Ok(Token::Int(n)));
+        }
+        if self.closes > 0 {
+            self.closes -= 1;
+            return Some(Ok(Token::Close));
+        }
+        self.tokens.next()
+    }
+
+    fn promote(&mut self, n: isize) {
+        self.int = Some(n);
+        self.closes += 1;
+    }
+}
+
+// Lists nested up to this depth are walked without touching the heap.
+const INLINE_DEPTH: usize = 32;
+
+struct PacketTokens<'a> {
+    next: Option<&'a Packet>,
+    inline: [slice::Iter<'a, Packet>; INLINE_DEPTH],
+    spilled: Vec<slice::Iter<'a, Packet>>,
+    depth: usize,
+}
+
+impl<'a> PacketTokens<'a> {
+    fn new(p: &'a Packet) -> Self {
+        PacketTokens {
+            next: Some(p),
+            inline: std::array::from_fn(|_| [].iter()),
+            spilled: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn push(&mut self, it: slice::Iter<'a, Packet>) {
+        if self.depth < INLINE_DEPTH {
+            self.inline[self.depth] = it;
+        } else {
+            self.spilled.push(it);
+        }
+        self.depth += 1;
+    }
+
+    fn top(&mut self) -> Option<&mut slice::Iter<'a, Packet>> {
+        match self.depth {
+            0 => None,
+            d if d <= INLINE_DEPTH => Some(&mut self.inline[d - 1]),
+            _ => self.spilled.last_mut(),
+        }
+    }
+
+    fn pop(&mut self) {
+        self.depth -= 1;
+        if self.depth >= INLINE_DEPTH {
+            self.spilled.pop();
+        }
+    }
+}
+
+impl<'a> Iterator for PacketTokens<'a> {
+    type Item = std::result::Result<Token, Infallible>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = match self.next.take() {
+            Some(p) => p,
+            None => match self.top()?.next() {
+                Some(p) => p,
+                None => {
+                    self.pop();
+                    return Some(Ok(Token::Close));
+                }
+            },
+        };
+        Some(Ok(match p {
+            Int(n) => Token::Int(*n),
+            List(ps) => {
+                self.push(ps.iter());
+                Token::Open
+            }
+        }))
+    }
+}
+
+// Tokenizes a packet directly from its text and checks the syntax on the way,
+// so that packets can be compared without building a tree.
+struct StrTokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    want_value: bool,
+}
+
+impl<'a> StrTokens<'a> {
+    fn new(s: &'a str) -> Self {
+        StrTokens {
+            bytes: s.as_bytes(),
+            pos: 0,
+            depth: 0,
+            want_value: true,
+        }
+    }
+
+    fn error(&self, msg: &str) -> Option<Result<Token>> {
+        Some(Err(format!("at offset {}: {}", self.pos, msg).into()))
+    }
+
+    fn int(&mut self) -> Option<Result<Token>> {
+        let start = self.pos;
+        let neg = self.bytes[self.pos] == b'-';
+        if neg {
+            self.pos += 1;
+        }
+        let mut n: isize = 0;
+        let digits = self.pos;
+        while let Some(d @ b'0'..=b'9') = self.bytes.get(self.pos) {
+            let d = (d - b'0') as isize;
+            n = match n.checked_mul(10).and_then(|n| {
+                if neg {
+                    n.checked_sub(d)
+                } else {
+                    n.checked_add(d)
+                }
+            }) {
+                Some(n) => n,
+                None => {
+                    return Some(Err(
+                        format!("at offset {}: number out of range", start).into()
+                    ))
+                }
+            };
+            self.pos += 1;
+        }
+        if self.pos == digits {
+            return Some(Err(format!("at offset {}: invalid number", start).into()));
+        }
+        self.want_value = false;
+        Some(Ok(Token::Int(n)))
+    }
+}
+
+impl<'a> Iterator for StrTokens<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.want_value && self.bytes.get(self.pos) == Some(&b',') {
+            if self.depth == 0 {
+                return self.error("unexpected ','");
+            }
+            self.pos += 1;
+            self.want_value = true;
+        }
+        let Some(&b) = self.bytes.get(self.pos) else {
+            if self.depth > 0 || self.pos == 0 {
+                return self.error("unexpected end of input");
+            }
+            return None;
+        };
+        if self.depth == 0 && self.pos > 0 {
+            return self.error("unexpected trailing input");
+        }
+        match b {
+            b'[' if self.want_value => {
+                self.pos += 1;
+                self.depth += 1;
+                Some(Ok(Token::Open))
+            }
+            b']' if self.depth > 0 && (!self.want_value || self.bytes[self.pos - 1] == b'[') => {
+                self.pos += 1;
+                self.depth -= 1;
+                self.want_value = false;
+                Some(Ok(Token::Close))
+            }
+            b'-' | b'0'..=b'9' if self.want_value && self.depth > 0 => self.int(),
+            _ => self.error("unexpected character"),
+        }
+    }
+}
+
+// Compares two packets in their text form without parsing them into a tree.
+// Both packets are read to the end, so that malformed input is reported even
+// when the order is decided early.
+pub fn cmp_str(a: &str, b: &str) -> Result<Ordering> {
+    let (mut left, mut right) = (StrTokens::new(a), StrTokens::new(b));
+    let ord = cmp_tokens(left.by_ref(), right.by_ref())?;
+    left.chain(right).try_for_each(|t| t.map(|_| ()))?;
+    Ok(ord)
+}
+
+// Stops at the first difference, for packets that passed validate_str.
+fn cmp_valid_str(a: &str, b: &str) -> Result<Ordering> {
+    cmp_tokens(StrTokens::new(a), StrTokens::new(b))
+}
+
+pub fn validate_str(s: &str) -> Result<()> {
+    StrTokens::new(s).try_for_each(|t| t.map(|_| ()))
+}
+
+pub fn compute1_str(p: &Path) -> Result<usize> {
+    let s = fs::read_to_string(p)?;
+    let lines = s.lines().filter(|l| !l.is_empty()).collect::<Vec<_>>();
+    let mut sum = 0;
+    for (i, ps) in lines.chunks(2).enumerate() {
+        if let &[a, b] = ps {
+            if cmp_str(a, b)? != Ordering::Greater {
+                sum += i + 1;
+            }
+        }
+    }
+    Ok(sum)
+}
+
+pub fn compute2_str(p: &Path) -> Result<usize> {
+    let s = fs::read_to_string(p)?;
+    let mut lines = s.lines().filter(|l| !l.is_empty()).collect::<Vec<_>>();
+    for l in &lines {
+        validate_str(l).map_err(|e| format!("invalid packet: {} error: {}", l, e))?;
+    }
+    lines.extend(["[[2]]", "[[6]]"]);
+    lines.sort_unstable_by(|a, b| cmp_valid_str(a, b).expect("packets are validated"));
+    let position = |div| {
+        lines
+            .binary_search_by(|l| cmp_valid_str(l, div).expect("packets are validated"))
+            .map(|i| i + 1)
+            .map_err(|_| format!("divider {} not found", div))
+    };
+    Ok(position("[[2]]")? * position("[[6]]")?)
+}
+
+pub fn compute2_external(p: &Path, run_len: usize) -> Result<usize> {
+    let sorter = ExternalSort {
+        run_len,
+        ..ExternalSort::default()
+    };
+    let dividers = ["[[2]]".parse()?, "[[6]]".parse()?];
+    Ok(sorter.divider_positions(p, &dividers)?.iter().product())
+}
+
+// Sorts packet files that do not fit into memory: the input is cut into runs
+// of `run_len` packets, which are sorted and spilled to files in `tmp_dir`,
+// and then merged with a heap of the current head of every run. At most
+// `max_runs` files are open at once; more runs are merged in several passes.
+pub struct ExternalSort {
+    pub run_len: usize,
+    pub max_runs: usize,
+    pub tmp_dir: PathBuf,
+}
+
+impl Default for ExternalSort {
+    fn default() -> Self {
+        ExternalSort {
+            run_len: 100_000,
+            max_runs: 64,
+            tmp_dir: env::temp_dir(),
+        }
+    }
+}
+
+impl ExternalSort {
+    pub fn sort(&self, input: &Path, output: &Path) -> Result<usize> {
+        let mut w = BufWriter::new(File::create(output)?);
+        let mut n = 0;
+        self.merge(input, &[], |p, _| {
+            n += 1;
+            writeln!(w, "{}", p)
+        })?;
+        w.flush()?;
+        Ok(n)
+    }
+
+    // Returns the 1-based positions the dividers take when they are sorted
+    // together with the packets of the input, in the order they were given.
+    pub fn divider_positions(&self, input: &Path, dividers: &[Packet]) -> Result<Vec<usize>> {
+        let mut positions = vec![0; dividers.len()];
+        let mut n = 0;
+        self.merge(input, dividers, |_, divider| {
+            n += 1;
+            if let Some(i) = divider {
+                positions[i] = n;
+            }
+            Ok(())
+        })?;
+        Ok(positions)
+    }
+
+    fn merge<F>(&self, input: &Path, dividers: &[Packet], sink: F) -> Result<()>
+    where
+        F: FnMut(&Packet, Option<usize>) -> io::Result<()>,
+    {
+        if self.run_len == 0 {
+            return Err("run length must be positive".into());
+        }
+        if self.max_runs < 2 {
+            return Err("at least two runs must be merged at once".into());
+        }
+        let mut runs = self.spill_runs(input)?;
+        while runs.files.len() > self.max_runs {
+            let files = std::mem::take(&mut runs.files);
+            for group in files.chunks(self.max_runs) {
+                let (path, mut w) = runs.create()?;
+                merge_files(group, &[], |p, _| writeln!(w, "{}", p))?;
+                w.flush()?;
+                runs.files.push(path);
+                for f in group {
+                    fs::remove_file(f)?;
+                }
+            }
+        }
+        merge_files(&runs.files, dividers, sink)
+    }
+
+    fn spill_runs(&self, input: &Path) -> Result<Runs> {
+        static SORTS: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let dir = self.tmp_dir.join(format!(
+            "day13-sort-{}-{}-{}",
+            process::id(),
+            nanos,
+            SORTS.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let mut runs = Runs {
+            dir,
+            files: Vec::new(),
+            created: 0,
+        };
+        let mut run = Vec::with_capacity(self.run_len);
+        for line in File::open(input).map(BufReader::new)?.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            run.push(line.parse::<Packet>()?);
+            if run.len() == self.run_len {
+                runs.spill(&mut run)?;
+            }
+        }
+        if !run.is_empty() {
+            runs.spill(&mut run)?;
+        }
+        Ok(runs)
+    }
+}
+
+// Merges sorted run files, together with the dividers, into one sorted
+// stream.
+fn merge_files<F>(files: &[PathBuf], dividers: &[Packet], mut sink: F) -> Result<()>
+where
+    F: FnMut(&Packet, Option<usize>) -> io::Result<()>,
+{
+    let mut readers = files
+        .iter()
+        .map(|f| File::open(f).map(BufReader::new).map(BufRead::lines))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut dividers = dividers.iter().cloned().enumerate().collect::<Vec<_>>();
+    dividers.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut dividers = dividers.into_iter();
+
+    let mut heap = BinaryHeap::new();
+    let mut push = |heap: &mut BinaryHeap<Head>, run: usize| -> Result<()> {
+        let packet = if run == readers.len() {
+            dividers.next().map(|(i, p)| (p, Some(i)))
+        } else {
+            match readers[run].next().transpose()? {
+                Some(l) => Some((l.parse()?, None)),
+                None => None,
+            }
+        };
+        if let Some((packet, divider)) = packet {
+            heap.push(Head {
+                packet,
+                run,
+                divider,
+            })
+        }
+        Ok(())
+    };
+    for run in 0..=files.len() {
+        push(&mut heap, run)?;
+    }
+    while let Some(head) = heap.pop() {
+        sink(&head.packet, head.divider)?;
+        push(&mut heap, head.run)?;
+    }
+    Ok(())
+}
+
+// Temporary run files, removed when dropped.
+struct Runs {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    created: usize,
+}
+
+impl Runs {
+    fn create(&mut self) -> io::Result<(PathBuf, BufWriter<File>)> {
+        let path = self.dir.join(format!("run-{}", self.created));
+        self.created += 1;
+        let w = BufWriter::new(File::create(&path)?);
+        Ok((path, w))
+    }
+
+    fn spill(&mut self, run: &mut Vec<Packet>) -> io::Result<()> {
+        run.sort();
+        let (path, mut w) = self.create()?;
+        for p in run.drain(..) {
+            writeln!(w, "{}", p)?;
+        }
+        w.flush()?;
+        self.files.push(path);
+        Ok(())
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Reversed ordering, so that the max-heap yields the smallest packet first.
+// Ties go to the lower run, which keeps the merge stable.
+struct Head {
+    packet: Packet,
+    run: usize,
+    divider: Option<usize>,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .packet
+            .cmp(&self.packet)
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,4 +855,190 @@ mod tests {
     fn day13_part2_input() {
         assert_eq!(compute2(Path::new(INPUT)).unwrap(), 24190);
     }
+
+    #[test]
+    fn day13_display_and_json() {
+        let lines = std::fs::read_to_string(INPUT).unwrap();
+        for l in lines.lines().filter(|l| !l.is_empty()) {
+            let p: Packet = l.parse().unwrap();
+            assert_eq!(p.to_string(), l);
+            assert_eq!(Packet::try_from(&JsonValue::from(&p)).unwrap(), p);
+        }
+        let v = JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Array(vec![])]);
+        assert_eq!(Packet::try_from(&v).unwrap().to_string(), "[1,[]]");
+        assert!(Packet::try_from(&JsonValue::Number(1.5)).is_err());
+        assert!(Packet::try_from(&JsonValue::Number(2f64.powi(63))).is_err());
+        assert_eq!(
+            Packet::try_from(&JsonValue::Number(-(2f64.powi(63)))).unwrap(),
+            Int(isize::MIN)
+        );
+        for n in [isize::MAX, isize::MIN, (1 << 53) + 1] {
+            let p = List(vec![Int(n)]);
+            assert_eq!(Packet::try_from(&JsonValue::from(&p)).unwrap(), p);
+        }
+        assert!(Packet::try_from(&JsonValue::String("1".into())).is_err());
+    }
+
+    #[test]
+    fn day13_explain() {
+        let explain = |a: &str, b: &str| {
+            let a: Packet = a.parse().unwrap();
+            let b: Packet = b.parse().unwrap();
+            let e = a.explain_cmp(&b);
+            assert_eq!(e.ordering, a.cmp(&b));
+            e
+        };
+        let e = explain("[1,1,3,1,1]", "[1,1,5,1,1]");
+        assert_eq!((e.path, e.rule), (vec![Step::Index(2)], Rule::Ints(3, 5)));
+        let e = explain("[[1],[2,3,4]]", "[[1],4]");
+        assert_eq!(
+            e.path,
+            vec![Step::Index(1), Step::Promote(Side::Right), Step::Index(0)]
+        );
+        assert_eq!(e.rule, Rule::Ints(2, 4));
+        assert_eq!(
+            e.to_string(),
+            "compare element 1; mixed types, convert right to list; compare element 0; 2 < 4, right order"
+        );
+        let e = explain("[9]", "[[8,7,6]]");
+        assert_eq!(e.ordering, Ordering::Greater);
+        let e = explain("[[4,4],4,4]", "[[4,4],4,4,4]");
+        assert_eq!((e.path, e.rule), (vec![], Rule::Lengths(3, 4)));
+        let e = explain("[[[]]]", "[[]]");
+        assert_eq!(e.rule, Rule::Lengths(1, 0));
+        assert_eq!(explain("[1,[2]]", "[1,[2]]").rule, Rule::Equal);
+        assert_eq!(explain("[1,[2]]", "[1,2]").rule, Rule::Equal);
+    }
+
+    #[test]
+    fn day13_parse_errors() {
+        let p: Packet = "[-1,[2,-30]]".parse().unwrap();
+        assert_eq!(p, List(vec![Int(-1), List(vec![Int(2), Int(-30)])]));
+        assert!("[-1]".parse::<Packet>().unwrap() < "[0]".parse().unwrap());
+
+        let err = |s: &str| s.parse::<Packet>().unwrap_err();
+        assert!(err("[1,2]]").contains("at offset 5: unexpected trailing input"));
+        assert!(err("[1,2] ").contains("at offset 5"));
+        assert!(err("[1,x]").contains("at offset 3: expected '[' or number, got 'x'"));
+        assert!(err("[1;2]").contains("at offset 2: expected ']' or ','"));
+        assert!(err("[1,[2").contains("at offset 5: unexpected end of input"));
+        assert!(err("[-]").contains("at offset 1: invalid number '-'"));
+        assert!(err("[1,]").contains("at offset 3"));
+        assert!(err("1").contains("at offset 0: expected '['"));
+        assert!(err("").contains("empty input"));
+
+        let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(deep.parse::<Packet>().is_ok());
+        let hostile = "[".repeat(1_000_000);
+        assert!(err(&hostile).contains("nesting deeper than"));
+    }
+
+    #[test]
+    fn day13_token_comparison() {
+        let s = std::fs::read_to_string(INPUT).unwrap();
+        let lines = s.lines().filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let packets = load(Path::new(INPUT)).unwrap();
+        for (a, pa) in lines.iter().zip(&packets).step_by(3) {
+            for (b, pb) in lines.iter().zip(&packets) {
+                let want = pa.explain_cmp(pb).ordering;
+                assert_eq!(pa.cmp(pb), want, "{} {}", a, b);
+                assert_eq!(cmp_str(a, b).unwrap(), want, "{} {}", a, b);
+            }
+        }
+
+        let nested = |depth: usize, n: isize| (0..depth).fold(Int(n), |p, _| List(vec![p]));
+        assert_eq!(nested(100, 1).cmp(&nested(100, 2)), Ordering::Less);
+        assert_eq!(nested(100, 1).cmp(&nested(40, 1)), Ordering::Equal);
+        assert_eq!(
+            nested(100, 1).cmp(&List(vec![nested(40, 1), Int(0)])),
+            Ordering::Less
+        );
+
+        let deep = |n: &str| "[".repeat(1_000_000) + n + &"]".repeat(1_000_000);
+        assert_eq!(cmp_str(&deep("3"), &deep("2")).unwrap(), Ordering::Greater);
+        assert_eq!(cmp_str(&deep("-3"), "[-2]").unwrap(), Ordering::Less);
+
+        assert!(cmp_str("[1,x]", "[1,2]").is_err());
+        assert!(cmp_str("[1,2]", "[1,2]]").is_err());
+        // malformed input after the deciding difference is still reported
+        assert!(cmp_str("[1,x", "[2]").is_err());
+        assert!(cmp_str("[2]", "[3,[").is_err());
+        let path = std::env::temp_dir().join("day13_compute1_str_invalid.txt");
+        std::fs::write(&path, "[1,x\n[2]\n").unwrap();
+        assert!(compute1_str(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        for invalid in [
+            "", "[", "[1,]", "[,1]", "[1 2]", "[[]][]", "1", "[-]", "[[1]",
+        ] {
+            assert!(validate_str(invalid).is_err(), "{}", invalid);
+        }
+        assert!(validate_str("[[],[-1,[2]],3]").is_ok());
+    }
+
+    #[test]
+    fn day13_str() {
+        assert_eq!(compute1_str(Path::new(EXAMPLE)).unwrap(), 13);
+        assert_eq!(compute1_str(Path::new(INPUT)).unwrap(), 5852);
+        assert_eq!(compute2_str(Path::new(EXAMPLE)).unwrap(), 140);
+        assert_eq!(compute2_str(Path::new(INPUT)).unwrap(), 24190);
+    }
+
+    #[test]
+    fn day13_external_sort() {
+        for run_len in [1, 7, 1000] {
+            assert_eq!(compute2_external(Path::new(EXAMPLE), run_len).unwrap(), 140);
+            assert_eq!(compute2_external(Path::new(INPUT), run_len).unwrap(), 24190);
+        }
+
+        let sorter = ExternalSort {
+            run_len: 10,
+            ..ExternalSort::default()
+        };
+        let out = env::temp_dir().join(format!("day13-sorted-{}", process::id()));
+        assert_eq!(sorter.sort(Path::new(INPUT), &out).unwrap(), 300);
+        let sorted = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        let mut want = load(Path::new(INPUT)).unwrap();
+        want.sort();
+        assert_eq!(
+            sorted.lines().collect::<Vec<_>>(),
+            want.iter().map(Packet::to_string).collect::<Vec<_>>()
+        );
+
+        let dividers = ["[[6]]", "[]", "[[2]]", "[99]"].map(|d| d.parse().unwrap());
+        let positions = sorter
+            .divider_positions(Path::new(EXAMPLE), &dividers)
+            .unwrap();
+        assert_eq!(positions, vec![15, 2, 11, 20]);
+        assert!(ExternalSort {
+            run_len: 0,
+            ..ExternalSort::default()
+        }
+        .divider_positions(Path::new(EXAMPLE), &dividers)
+        .is_err());
+
+        // 300 runs of one packet merged two or three at a time
+        for max_runs in [2, 3] {
+            let sorter = ExternalSort {
+                run_len: 1,
+                max_runs,
+                ..ExternalSort::default()
+            };
+            assert_eq!(sorter.sort(Path::new(INPUT), &out).unwrap(), 300);
+            assert_eq!(std::fs::read_to_string(&out).unwrap(), sorted);
+            std::fs::remove_file(&out).unwrap();
+            assert_eq!(
+                sorter
+                    .divider_positions(Path::new(EXAMPLE), &dividers)
+                    .unwrap(),
+                vec![15, 2, 11, 20]
+            );
+        }
+        assert!(ExternalSort {
+            max_runs: 1,
+            ..ExternalSort::default()
+        }
+        .divider_positions(Path::new(EXAMPLE), &dividers)
+        .is_err());
+    }
 }
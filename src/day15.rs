@@ -1,46 +1,29 @@
+use crate::intervals::IntervalSet;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Lines};
+use std::io::{self, BufRead, BufReader, Lines, Write};
 use std::ops::RangeInclusive;
 use std::path::Path;
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn compute1(p: &Path, y: isize) -> Result<usize> {
-    let mut v: Vec<(isize, isize)> = load(p)?
+    Ok(load(p)?
         .iter()
         .map(|s| s.x_range_without_beacon(y))
-        .filter(|r| !r.is_empty())
-        .flat_map(|r| vec![(*r.start(), 1), (*r.end() + 1, -1)])
-        .collect();
-    v.sort();
-    let mut res: Vec<(isize, isize)> = Vec::new();
-    let mut i = isize::MIN;
-    for sp in v {
-        if sp.0 != i {
-            i = sp.0;
-            res.push(sp);
-        } else {
-            let i = res.len() - 1;
-            res[i].1 += sp.1
-        }
-    }
-    let mut sum = 0;
-    let mut lvl = 0;
-    let mut last = isize::MIN;
-    for sl in res {
-        if last > isize::MIN && lvl > 0 {
-            sum += (last..sl.0).len();
-        }
-        last = sl.0;
-        lvl += sl.1;
-    }
-    Ok(sum)
+        .collect::<IntervalSet>()
+        .len())
 }
 
 pub fn compute2(p: &Path, rng: RangeInclusive<isize>) -> Result<isize> {
-    let sensors = load(p)?;
+    tuning_frequency(&load(p)?, rng)
+}
+
+fn tuning_frequency(sensors: &[Sensor], rng: RangeInclusive<isize>) -> Result<isize> {
     let (lines1, lines2): (Vec<Line>, Vec<Line>) = sensors
         .iter()
         .flat_map(|s| s.lines())
@@ -59,6 +42,560 @@ pub fn compute2(p: &Path, rng: RangeInclusive<isize>) -> Result<isize> {
         .ok_or("not found".into())
 }
 
+#[derive(Debug)]
+pub struct ScanReport {
+    pub gaps: Vec<(isize, IntervalSet)>,
+    pub elapsed: Duration,
+}
+
+// Brute-force cross-check for compute2: computes the uncovered cells of every
+// row separately, as in compute1, with the rows split across threads.
+pub fn scan_rows(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+    threads: usize,
+) -> Result<ScanReport> {
+    Ok(scan(&load(p)?, xs, ys, threads))
+}
+
+fn scan(
+    sensors: &[Sensor],
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+    threads: usize,
+) -> ScanReport {
+    let start = Instant::now();
+    let threads = threads.max(1) as isize;
+    let rows = (ys.end() - ys.start() + 1).max(0);
+    let chunk = (rows + threads - 1) / threads;
+    let mut gaps = thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|i| ys.start() + i * chunk)
+            .map(|y0| (y0, (y0 + chunk - 1).min(*ys.end())))
+            .map(|(y0, y1)| {
+                let xs = xs.clone();
+                scope.spawn(move || {
+                    (y0..=y1)
+                        .map(|y| {
+                            (
+                                y,
+                                row_coverage(&Manhattan, sensors, y).complement(xs.clone()),
+                            )
+                        })
+                        .filter(|(_, free)| !free.is_empty())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("scan thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    gaps.sort_by_key(|(y, _)| *y);
+    ScanReport {
+        gaps,
+        elapsed: start.elapsed(),
+    }
+}
+
+// An uncovered area, as a rectangle in rotated coordinates u = x + y and
+// v = x - y, in which every sensor's range is an axis-aligned square. In the
+// grid, such a rectangle is a diamond, clipped to the searched region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gap {
+    pub u: RangeInclusive<isize>,
+    pub v: RangeInclusive<isize>,
+}
+
+impl Gap {
+    pub fn cells(
+        &self,
+        xs: &RangeInclusive<isize>,
+        ys: &RangeInclusive<isize>,
+    ) -> impl Iterator<Item = (isize, isize)> + '_ {
+        let (x0, x1, y0, y1) = (*xs.start(), *xs.end(), *ys.start(), *ys.end());
+        self.u.clone().flat_map(move |u| {
+            // x = (u + v) / 2 and y = (u - v) / 2 must be integers in range
+            let lo = *[*self.v.start(), 2 * x0 - u, u - 2 * y1]
+                .iter()
+                .max()
+                .unwrap();
+            let hi = *[*self.v.end(), 2 * x1 - u, u - 2 * y0]
+                .iter()
+                .min()
+                .unwrap();
+            let lo = lo + (lo - u).rem_euclid(2);
+            (lo..=hi)
+                .step_by(2)
+                .map(move |v| ((u + v) / 2, (u - v) / 2))
+        })
+    }
+}
+
+pub fn uncovered(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+) -> Result<Vec<Gap>> {
+    check_region(&xs, &ys)?;
+    Ok(uncovered_by(&load(p)?, xs, ys))
+}
+
+// Regions are limited so that rotated coordinates, and one past their ends,
+// cannot overflow.
+const MAX_COORD: isize = isize::MAX / 4;
+
+fn check_region(xs: &RangeInclusive<isize>, ys: &RangeInclusive<isize>) -> Result<()> {
+    let limit = -MAX_COORD..=MAX_COORD;
+    match [xs.start(), xs.end(), ys.start(), ys.end()]
+        .iter()
+        .all(|c| limit.contains(c))
+    {
+        true => Ok(()),
+        false => Err(format!(
+            "region {:?} x {:?} exceeds coordinates of +/-{}",
+            xs, ys, MAX_COORD
+        )
+        .into()),
+    }
+}
+
+fn uncovered_by(
+    sensors: &[Sensor],
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+) -> Vec<Gap> {
+    let squares = sensors
+        .iter()
+        .map(|s| {
+            let (u, v, r) = (s.pos.x + s.pos.y, s.pos.x - s.pos.y, s.radius());
+            (u - r..=u + r, v - r..=v + r)
+        })
+        .collect::<Vec<_>>();
+    let (us, vs) = (
+        xs.start() + ys.start()..=xs.end() + ys.end(),
+        xs.start() - ys.end()..=xs.end() - ys.start(),
+    );
+    let mut gaps = sweep(&squares, us, vs)
+        .into_iter()
+        .map(|(u, v)| Gap { u, v })
+        .collect::<Vec<_>>();
+    gaps.retain(|g| g.cells(&xs, &ys).next().is_some());
+    gaps
+}
+
+// Finds the areas not covered by any of the given rectangles: the outer axis
+// is cut into slabs at the rectangles' edges, and the free inner intervals of
+// each slab are stacked into rectangles.
+fn sweep(
+    rects: &[(RangeInclusive<isize>, RangeInclusive<isize>)],
+    outer: RangeInclusive<isize>,
+    inner: RangeInclusive<isize>,
+) -> Vec<(RangeInclusive<isize>, RangeInclusive<isize>)> {
+    let mut bounds = rects
+        .iter()
+        .flat_map(|(o, _)| [Some(*o.start()), o.end().checked_add(1)])
+        .flatten()
+        .filter(|o| outer.contains(o))
+        // callers keep the outer axis within check_region's limits
+        .chain([*outer.start(), outer.end() + 1])
+        .collect::<Vec<_>>();
+    bounds.sort();
+    bounds.dedup();
+    stack(bounds.windows(2).map(|slab| {
+        let (o0, o1) = (slab[0], slab[1] - 1);
+        let free = rects
+            .iter()
+            .filter(|(o, _)| o.start() <= &o0 && o.end() >= &o1)
+            .map(|(_, i)| i.clone())
+            .collect::<IntervalSet>()
+            .complement(inner.clone());
+        (o0..=o1, free)
+    }))
+}
+
+// Stacks the free intervals of slabs into rectangles. A rectangle grows as
+// long as the next adjacent slab has exactly the same free interval.
+fn stack<I>(slabs: I) -> Vec<(RangeInclusive<isize>, RangeInclusive<isize>)>
+where
+    I: IntoIterator<Item = (RangeInclusive<isize>, IntervalSet)>,
+{
+    let mut done = Vec::new();
+    let mut open: Vec<(RangeInclusive<isize>, RangeInclusive<isize>)> = Vec::new();
+    for (slab, free) in slabs {
+        let (extended, closed): (Vec<_>, Vec<_>) = open.drain(..).partition(|(o, i)| {
+            o.end().checked_add(1) == Some(*slab.start()) && free.ranges().contains(i)
+        });
+        done.extend(closed);
+        open = free
+            .ranges()
+            .iter()
+            .map(|i| match extended.iter().find(|(_, j)| i == j) {
+                Some((o, _)) => (*o.start()..=*slab.end(), i.clone()),
+                None => (slab.clone(), i.clone()),
+            })
+            .collect();
+    }
+    done.extend(open);
+    done
+}
+
+pub fn uncovered_cells(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+) -> Result<Vec<(isize, isize)>> {
+    let mut cells = uncovered(p, xs.clone(), ys.clone())?
+        .iter()
+        .flat_map(|g| g.cells(&xs, &ys).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    cells.sort();
+    Ok(cells)
+}
+
+// Cells not covered by any sensor, as rectangles in the grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub xs: RangeInclusive<isize>,
+    pub ys: RangeInclusive<isize>,
+}
+
+// How far a sensor reaches. A sensor covers every cell that is at most as far
+// away as its closest beacon.
+pub trait Metric {
+    // The reach of a sensor whose beacon is at the given offset, in units
+    // that suit half_width (squared distances for the Euclidean metric).
+    fn reach(&self, dx: isize, dy: isize) -> isize;
+
+    // The largest |dx| covered in the row at offset dy, if any.
+    fn half_width(&self, reach: isize, dy: isize) -> Option<isize>;
+
+    fn gaps(
+        &self,
+        sensors: &[Sensor],
+        xs: RangeInclusive<isize>,
+        ys: RangeInclusive<isize>,
+    ) -> Vec<Rect> {
+        let rows = ys.map(|y| {
+            let free = row_coverage(self, sensors, y).complement(xs.clone());
+            (y..=y, free)
+        });
+        stack(rows)
+            .into_iter()
+            .map(|(ys, xs)| Rect { xs, ys })
+            .collect()
+    }
+}
+
+pub struct Manhattan;
+
+impl Metric for Manhattan {
+    fn reach(&self, dx: isize, dy: isize) -> isize {
+        dx.abs() + dy.abs()
+    }
+
+    fn half_width(&self, reach: isize, dy: isize) -> Option<isize> {
+        Some(reach - dy.abs()).filter(|w| *w >= 0)
+    }
+
+    // Diamonds are squares in rotated coordinates, see `uncovered`.
+    fn gaps(
+        &self,
+        sensors: &[Sensor],
+        xs: RangeInclusive<isize>,
+        ys: RangeInclusive<isize>,
+    ) -> Vec<Rect> {
+        let mut rows: BTreeMap<isize, IntervalSet> = BTreeMap::new();
+        for g in uncovered_by(sensors, xs.clone(), ys.clone()) {
+            // cells of a row y satisfy u = v + 2y and x = v + y
+            let y0 = (g.u.start() - g.v.end()).div_euclid(2).max(*ys.start());
+            let y1 = (g.u.end() - g.v.start()).div_euclid(2).min(*ys.end());
+            for y in y0..=y1 {
+                let v0 = (*g.v.start()).max(g.u.start() - 2 * y);
+                let v1 = (*g.v.end()).min(g.u.end() - 2 * y);
+                let run = (v0 + y).max(*xs.start())..=(v1 + y).min(*xs.end());
+                rows.entry(y).or_default().insert(run);
+            }
+        }
+        stack(rows.into_iter().map(|(y, free)| (y..=y, free)))
+            .into_iter()
+            .map(|(ys, xs)| Rect { xs, ys })
+            .collect()
+    }
+}
+
+pub struct Chebyshev;
+
+impl Metric for Chebyshev {
+    fn reach(&self, dx: isize, dy: isize) -> isize {
+        dx.abs().max(dy.abs())
+    }
+
+    fn half_width(&self, reach: isize, dy: isize) -> Option<isize> {
+        Some(reach).filter(|r| dy.abs() <= *r)
+    }
+
+    // The ranges are axis-aligned squares, so the sweep works on the grid.
+    fn gaps(
+        &self,
+        sensors: &[Sensor],
+        xs: RangeInclusive<isize>,
+        ys: RangeInclusive<isize>,
+    ) -> Vec<Rect> {
+        let squares = sensors
+            .iter()
+            .map(|s| {
+                let r = s.reach(self);
+                (s.pos.y - r..=s.pos.y + r, s.pos.x - r..=s.pos.x + r)
+            })
+            .collect::<Vec<_>>();
+        sweep(&squares, ys, xs)
+            .into_iter()
+            .map(|(ys, xs)| Rect { xs, ys })
+            .collect()
+    }
+}
+
+// How the distance to the beacon is rounded to obtain the sensor's radius.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Exact,
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+pub struct Euclidean(pub Rounding);
+
+impl Metric for Euclidean {
+    fn reach(&self, dx: isize, dy: isize) -> isize {
+        let d2 = dx * dx + dy * dy;
+        let r = d2.isqrt();
+        match self.0 {
+            Rounding::Exact => d2,
+            Rounding::Floor => r * r,
+            Rounding::Ceil if r * r == d2 => d2,
+            Rounding::Ceil => (r + 1) * (r + 1),
+            // sqrt(d2) >= r + 1/2 iff d2 > r^2 + r for integers
+            Rounding::Nearest if d2 > r * r + r => (r + 1) * (r + 1),
+            Rounding::Nearest => r * r,
+        }
+    }
+
+    fn half_width(&self, reach: isize, dy: isize) -> Option<isize> {
+        Some(reach - dy * dy).filter(|w| *w >= 0).map(isize::isqrt)
+    }
+}
+
+pub fn compute1_with<M: Metric>(p: &Path, metric: &M, y: isize) -> Result<usize> {
+    let sensors = load(p)?;
+    let beacons = sensors
+        .iter()
+        .filter(|s| s.beacon.y == y)
+        .map(|s| s.beacon.x..=s.beacon.x)
+        .collect::<IntervalSet>();
+    Ok(row_coverage(metric, &sensors, y).difference(&beacons).len())
+}
+
+pub fn gaps_with<M: Metric>(
+    p: &Path,
+    metric: &M,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+) -> Result<Vec<Rect>> {
+    check_region(&xs, &ys)?;
+    let mut gaps = metric.gaps(&load(p)?, xs, ys);
+    gaps.sort_by_key(|r| (*r.ys.start(), *r.xs.start()));
+    Ok(gaps)
+}
+
+fn row_coverage<M: Metric + ?Sized>(metric: &M, sensors: &[Sensor], y: isize) -> IntervalSet {
+    sensors
+        .iter()
+        .filter_map(|s| {
+            let w = metric.half_width(s.reach(metric), y - s.pos.y)?;
+            Some(s.pos.x - w..=s.pos.x + w)
+        })
+        .collect()
+}
+
+// Draws the region like the puzzle's example: sensors as S, beacons as B and,
+// with `coverage`, cells in reach of a sensor as #.
+pub fn render_ascii(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+    coverage: bool,
+) -> Result<String> {
+    let sensors = load(p)?;
+    let mut res = String::new();
+    for y in ys {
+        for x in xs.clone() {
+            let pos = Pos { x, y };
+            res.push(if sensors.iter().any(|s| s.pos == pos) {
+                'S'
+            } else if sensors.iter().any(|s| s.beacon == pos) {
+                'B'
+            } else if coverage && sensors.iter().any(|s| s.contains(&pos)) {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        res.push('\n');
+    }
+    Ok(res)
+}
+
+// Renders a binary PPM of the given width, sampling one cell per pixel. Each
+// sensor's diamond gets its own color, beacons are white and uncovered cells,
+// which would otherwise vanish when scaled down, are marked with a red cross.
+pub fn render_ppm<W: Write>(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+    width: usize,
+    w: &mut W,
+) -> Result<()> {
+    check_region(&xs, &ys)?;
+    let sensors = load(p)?;
+    let (cols, rows) = (xs.end() - xs.start() + 1, ys.end() - ys.start() + 1);
+    if cols <= 0 || rows <= 0 || width == 0 {
+        return Err("empty image".into());
+    }
+    let height = ((width as isize * rows + cols - 1) / cols).max(1) as usize;
+    let cell = |px: usize, py: usize| Pos {
+        x: xs.start() + (2 * px as isize + 1) * cols / (2 * width as isize),
+        y: ys.start() + (2 * py as isize + 1) * rows / (2 * height as isize),
+    };
+    let pixel = |pos: &Pos| {
+        let x = (pos.x - xs.start()) * width as isize / cols;
+        let y = (pos.y - ys.start()) * height as isize / rows;
+        (x as usize, y as usize)
+    };
+
+    let mut img = vec![[0u8; 3]; width * height];
+    for py in 0..height {
+        for px in 0..width {
+            let pos = cell(px, py);
+            if let Some(i) = sensors.iter().position(|s| s.contains(&pos)) {
+                img[py * width + px] = color(i);
+            }
+        }
+    }
+    for s in &sensors {
+        if xs.contains(&s.beacon.x) && ys.contains(&s.beacon.y) {
+            let (x, y) = pixel(&s.beacon);
+            img[y * width + x] = [255, 255, 255];
+        }
+    }
+    for gap in Manhattan.gaps(&sensors, xs.clone(), ys.clone()) {
+        let (x, y) = pixel(&Pos {
+            x: *gap.xs.start(),
+            y: *gap.ys.start(),
+        });
+        for d in -2..=2isize {
+            for (cx, cy) in [(x as isize + d, y as isize), (x as isize, y as isize + d)] {
+                if (0..width as isize).contains(&cx) && (0..height as isize).contains(&cy) {
+                    img[cy as usize * width + cx as usize] = [255, 0, 0];
+                }
+            }
+        }
+    }
+
+    write!(w, "P6\n{} {}\n255\n", width, height)?;
+    w.write_all(&img.concat())?;
+    Ok(())
+}
+
+// Renders the region as SVG, which stays small for the full 4000000-wide
+// area: one polygon per sensor, a circle per beacon and a marked rectangle
+// per uncovered area.
+pub fn render_svg<W: Write>(
+    p: &Path,
+    xs: RangeInclusive<isize>,
+    ys: RangeInclusive<isize>,
+    w: &mut W,
+) -> Result<()> {
+    check_region(&xs, &ys)?;
+    let sensors = load(p)?;
+    let (cols, rows) = (xs.end() - xs.start() + 1, ys.end() - ys.start() + 1);
+    let mark = (cols.max(rows) / 100).max(1);
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        xs.start(),
+        ys.start(),
+        cols,
+        rows
+    )?;
+    writeln!(
+        w,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="black"/>"#,
+        xs.start(),
+        ys.start(),
+        cols,
+        rows
+    )?;
+    for (i, s) in sensors.iter().enumerate() {
+        // the outline runs along the outer corners of the diamond's cells
+        let (x, y, r) = (s.pos.x, s.pos.y, s.radius());
+        let [red, green, blue] = color(i);
+        writeln!(
+            w,
+            r#"<polygon points="{},{} {},{} {},{} {},{}" fill="rgb({},{},{})" fill-opacity="0.6"/>"#,
+            x,
+            y - r,
+            x + r + 1,
+            y,
+            x + 1,
+            y + r + 1,
+            x - r,
+            y + 1,
+            red,
+            green,
+            blue
+        )?;
+    }
+    for s in &sensors {
+        writeln!(
+            w,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="white"/>"#,
+            s.beacon.x, s.beacon.y, mark
+        )?;
+    }
+    for gap in Manhattan.gaps(&sensors, xs.clone(), ys.clone()) {
+        writeln!(
+            w,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="red" stroke="red" stroke-width="{}"/>"#,
+            gap.xs.start(),
+            gap.ys.start(),
+            gap.xs.end() - gap.xs.start() + 1,
+            gap.ys.end() - gap.ys.start() + 1,
+            mark
+        )?;
+    }
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
+// Well-separated colors, by stepping the hue by the golden angle.
+fn color(i: usize) -> [u8; 3] {
+    let h = (i as f64 * 137.508) % 360.0 / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c| (55.0 + 160.0 * c) as u8)
+}
+
 fn load(p: &Path) -> Result<Vec<Sensor>> {
     Ok(File::open(p)
         .map_err(io::Error::into)
@@ -105,7 +642,7 @@ impl Line {
     }
 }
 
-struct Sensor {
+pub struct Sensor {
     pos: Pos,
     beacon: Pos,
 }
@@ -122,6 +659,14 @@ impl Sensor {
         return self.pos.distance(pos) <= self.pos.distance(&self.beacon);
     }
 
+    fn radius(&self) -> isize {
+        self.pos.distance(&self.beacon)
+    }
+
+    fn reach<M: Metric + ?Sized>(&self, metric: &M) -> isize {
+        metric.reach(self.beacon.x - self.pos.x, self.beacon.y - self.pos.y)
+    }
+
     fn lines(&self) -> Vec<Line> {
         let d = self.pos.distance(&self.beacon) + 1;
         let mut res = Vec::new();
@@ -190,4 +735,268 @@ mod tests {
             11600823139120
         );
     }
+
+    #[test]
+    fn day15_uncovered() {
+        assert_eq!(
+            uncovered_cells(Path::new(EXAMPLE), 0..=20, 0..=20).unwrap(),
+            vec![(14, 11)]
+        );
+        assert_eq!(
+            uncovered_cells(Path::new(INPUT), 0..=4000000, 0..=4000000).unwrap(),
+            vec![(2900205, 3139120)]
+        );
+        let huge = isize::MAX - 1..=isize::MAX;
+        assert!(uncovered(Path::new(EXAMPLE), 0..=20, huge.clone()).is_err());
+        assert!(uncovered(Path::new(EXAMPLE), isize::MIN..=0, 0..=20).is_err());
+        assert!(gaps_with(Path::new(EXAMPLE), &Chebyshev, huge.clone(), 0..=20).is_err());
+        let far = isize::MAX / 4 - 10..=isize::MAX / 4;
+        assert_eq!(
+            uncovered_cells(Path::new(EXAMPLE), far.clone(), far.clone())
+                .unwrap()
+                .len(),
+            121
+        );
+        assert!(uncovered(Path::new(EXAMPLE), 0..=10, 0..=10)
+            .unwrap()
+            .is_empty());
+
+        // outside of the sensors' reach, whole areas are uncovered
+        let cells = uncovered_cells(Path::new(EXAMPLE), 0..=20, -12..=-8).unwrap();
+        let sensors = load(Path::new(EXAMPLE)).unwrap();
+        let mut brute = (-12..=-8)
+            .flat_map(|y| (0..=20).map(move |x| (x, y)))
+            .filter(|&(x, y)| !sensors.iter().any(|s| s.contains(&Pos { x, y })))
+            .collect::<Vec<_>>();
+        brute.sort();
+        assert!(!cells.is_empty());
+        assert_eq!(cells, brute);
+    }
+
+    fn brute_gaps<M: Metric>(
+        metric: &M,
+        xs: RangeInclusive<isize>,
+        ys: RangeInclusive<isize>,
+    ) -> Vec<(isize, isize)> {
+        let sensors = load(Path::new(EXAMPLE)).unwrap();
+        let mut cells = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                !sensors.iter().any(|s| {
+                    metric
+                        .half_width(s.reach(metric), y - s.pos.y)
+                        .is_some_and(|w| (x - s.pos.x).abs() <= w)
+                })
+            })
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells
+    }
+
+    fn cells(rects: &[Rect]) -> Vec<(isize, isize)> {
+        let mut cells = rects
+            .iter()
+            .flat_map(|r| r.ys.clone().flat_map(|y| r.xs.clone().map(move |x| (x, y))))
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn day15_metrics() {
+        let p = Path::new(EXAMPLE);
+        assert_eq!(compute1_with(p, &Manhattan, 10).unwrap(), 26);
+        assert_eq!(
+            compute1_with(Path::new(INPUT), &Manhattan, 2000000).unwrap(),
+            4873353
+        );
+        assert_eq!(
+            gaps_with(p, &Manhattan, 0..=20, 0..=20).unwrap(),
+            vec![Rect {
+                xs: 14..=14,
+                ys: 11..=11
+            }]
+        );
+        assert_eq!(
+            gaps_with(Path::new(INPUT), &Manhattan, 0..=4000000, 0..=4000000).unwrap(),
+            vec![Rect {
+                xs: 2900205..=2900205,
+                ys: 3139120..=3139120
+            }]
+        );
+
+        let (xs, ys) = (-10..=30, -15..=30);
+        let want = brute_gaps(&Manhattan, xs.clone(), ys.clone());
+        assert_eq!(
+            cells(&gaps_with(p, &Manhattan, xs.clone(), ys.clone()).unwrap()),
+            want
+        );
+        assert_eq!(
+            cells(&Manhattan.gaps(&load(p).unwrap(), xs.clone(), ys.clone())),
+            want
+        );
+        let want = brute_gaps(&Chebyshev, xs.clone(), ys.clone());
+        assert_eq!(
+            cells(&gaps_with(p, &Chebyshev, xs.clone(), ys.clone()).unwrap()),
+            want
+        );
+        for rounding in [
+            Rounding::Exact,
+            Rounding::Floor,
+            Rounding::Ceil,
+            Rounding::Nearest,
+        ] {
+            let metric = Euclidean(rounding);
+            let want = brute_gaps(&metric, xs.clone(), ys.clone());
+            assert_eq!(
+                cells(&gaps_with(p, &metric, xs.clone(), ys.clone()).unwrap()),
+                want
+            );
+        }
+    }
+
+    #[test]
+    fn day15_metric_rows() {
+        // Chebyshev squares cover more than the diamonds, circles in between
+        let p = Path::new(EXAMPLE);
+        let manhattan = compute1_with(p, &Manhattan, 10).unwrap();
+        let chebyshev = compute1_with(p, &Chebyshev, 10).unwrap();
+        let euclidean = compute1_with(p, &Euclidean(Rounding::Exact), 10).unwrap();
+        assert!(manhattan <= euclidean && euclidean <= chebyshev);
+
+        let e = |r, dx, dy| Euclidean(r).reach(dx, dy);
+        assert_eq!(e(Rounding::Exact, 3, 4), 25);
+        assert_eq!(e(Rounding::Exact, 2, 3), 13);
+        assert_eq!(e(Rounding::Floor, 2, 3), 9);
+        assert_eq!(e(Rounding::Ceil, 2, 3), 16);
+        assert_eq!(e(Rounding::Nearest, 2, 3), 16);
+        assert_eq!(e(Rounding::Nearest, 2, 2), 9);
+        assert_eq!(Euclidean(Rounding::Exact).half_width(25, 3), Some(4));
+        assert_eq!(Euclidean(Rounding::Exact).half_width(25, 6), None);
+    }
+
+    #[test]
+    fn day15_scan() {
+        let report = scan_rows(Path::new(EXAMPLE), 0..=20, 0..=20, 4).unwrap();
+        assert_eq!(report.gaps, vec![(11, IntervalSet::from(14..=14))]);
+        let report = scan_rows(Path::new(INPUT), 0..=4000000, 3139000..=3139200, 3).unwrap();
+        assert_eq!(
+            report.gaps,
+            vec![(3139120, IntervalSet::from(2900205..=2900205))]
+        );
+        // more threads than rows
+        let report = scan_rows(Path::new(EXAMPLE), 0..=20, 0..=20, 100).unwrap();
+        assert_eq!(report.gaps.len(), 1);
+    }
+
+    // Places random sensors around a hidden cell, each reaching just short of
+    // it, and checks compute2 against the scan whenever that cell is the only
+    // gap.
+    #[test]
+    fn day15_scan_cross_check() {
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut rand = |n: isize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as isize
+        };
+        let mut checked = 0;
+        for _ in 0..200 {
+            let hidden = Pos {
+                x: rand(41),
+                y: rand(41),
+            };
+            let sensors = (0..25)
+                .filter_map(|_| {
+                    let pos = Pos {
+                        x: rand(61) - 10,
+                        y: rand(61) - 10,
+                    };
+                    let r = pos.distance(&hidden) - 1;
+                    if r < 0 {
+                        return None;
+                    }
+                    let dx = rand(r + 1);
+                    Some(Sensor {
+                        pos,
+                        beacon: Pos {
+                            x: pos.x + dx,
+                            y: pos.y - (r - dx),
+                        },
+                    })
+                })
+                .collect::<Vec<_>>();
+            let report = scan(&sensors, 0..=40, 0..=40, 4);
+            if report.gaps == vec![(hidden.y, IntervalSet::from(hidden.x..=hidden.x))] {
+                assert_eq!(
+                    tuning_frequency(&sensors, 0..=40).unwrap(),
+                    4000000 * hidden.x + hidden.y
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn day15_render_ascii() {
+        let want = "\
+        ....S.......................\n\
+        ......................S.....\n\
+        ...............S............\n\
+        ................SB..........\n\
+        ............................\n\
+        ............................\n\
+        ............................\n\
+        ..........S.......S.........\n\
+        ............................\n\
+        ............................\n\
+        ....B.......................\n\
+        ..S.........................\n\
+        ............................\n\
+        ............................\n\
+        ..............S.......S.....\n\
+        B...........................\n\
+        ...........SB...............\n\
+        ................S..........B\n\
+        ....S.......................\n\
+        ............................\n\
+        ............S......S........\n\
+        ............................\n\
+        .......................B....\n";
+        assert_eq!(
+            render_ascii(Path::new(EXAMPLE), -2..=25, 0..=22, false).unwrap(),
+            want
+        );
+        let row = render_ascii(Path::new(EXAMPLE), -10..=40, 10..=10, true).unwrap();
+        assert_eq!(row.matches('#').count(), 26);
+        assert_eq!(row.matches('B').count(), 1);
+        let area = render_ascii(Path::new(EXAMPLE), 0..=20, 0..=20, true).unwrap();
+        assert_eq!(area.lines().nth(11).unwrap().find('.'), Some(14));
+    }
+
+    #[test]
+    fn day15_render_images() {
+        let mut ppm = Vec::new();
+        render_ppm(Path::new(EXAMPLE), 0..=20, 0..=20, 42, &mut ppm).unwrap();
+        let header = b"P6\n42 42\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 42 * 42 * 3);
+        assert!(ppm[header.len()..].chunks(3).any(|c| c == [255, 0, 0]));
+        let mut ppm = Vec::new();
+        render_ppm(Path::new(INPUT), 0..=4000000, 0..=4000000, 20, &mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n20 20\n255\n"));
+        assert!(render_ppm(Path::new(EXAMPLE), 0..=20, 0..=20, 0, &mut Vec::new()).is_err());
+
+        let mut svg = Vec::new();
+        render_svg(Path::new(INPUT), 0..=4000000, 0..=4000000, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        let sensors = load(Path::new(INPUT)).unwrap().len();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<polygon").count(), sensors);
+        assert_eq!(svg.matches("<circle").count(), sensors);
+        assert!(svg.contains(r#"<rect x="2900205" y="3139120" width="1" height="1""#));
+    }
 }
@@ -1,6 +1,7 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Lines};
+use std::io::{self, BufRead, BufReader, Lines, Write};
 use std::path::Path;
 use std::str::FromStr;
 
@@ -14,6 +15,313 @@ pub fn compute1(p: &Path) -> Result<usize> {
     Ok(2 * v.len() - l)
 }
 
+// Counts exposed faces by looking up the six neighbours of every cube, in a
+// bit-packed grid over the bounding box when it is small enough, and in a
+// hash set otherwise.
+pub fn compute1_grid(p: &Path) -> Result<usize> {
+    Ok(count_exposed(&load(p)?))
+}
+
+fn count_exposed(points: &[Point]) -> usize {
+    match Grid::new(points) {
+        Some(grid) => grid.exposed(points),
+        None => {
+            let cubes = points.iter().copied().collect::<HashSet<_>>();
+            cubes
+                .iter()
+                .map(|p| p.neighbors().iter().filter(|n| !cubes.contains(n)).count())
+                .sum()
+        }
+    }
+}
+
+// The dense grid may use at most this many cells per cube, so that its memory
+// stays comparable to a hash set of the same cubes.
+const MAX_CELLS_PER_CUBE: usize = 512;
+
+struct Grid {
+    lo: Point,
+    dims: [usize; 3],
+    bits: Vec<u64>,
+}
+
+impl Grid {
+    fn new(points: &[Point]) -> Option<Grid> {
+        let (lo, hi) = bounds(points)?;
+        let dims = [
+            hi.x.abs_diff(lo.x),
+            hi.y.abs_diff(lo.y),
+            hi.z.abs_diff(lo.z),
+        ]
+        .map(|d| d.saturating_add(1));
+        let cells = dims.iter().try_fold(1usize, |acc, d| acc.checked_mul(*d))?;
+        if cells > points.len().saturating_mul(MAX_CELLS_PER_CUBE) {
+            return None;
+        }
+        let mut grid = Grid {
+            lo,
+            dims,
+            bits: vec![0; cells.div_ceil(64)],
+        };
+        for p in points {
+            let i = grid.index(p)?;
+            grid.bits[i / 64] |= 1 << (i % 64);
+        }
+        Some(grid)
+    }
+
+    fn index(&self, p: &Point) -> Option<usize> {
+        let [x, y, z] = [p.x - self.lo.x, p.y - self.lo.y, p.z - self.lo.z];
+        let [dx, dy, dz] = self.dims;
+        if x < 0 || y < 0 || z < 0 || x as usize >= dx || y as usize >= dy || z as usize >= dz {
+            return None;
+        }
+        Some((x as usize * dy + y as usize) * dz + z as usize)
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        self.index(p)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    // Duplicate points are skipped with a second bitset of the cubes already
+    // counted.
+    fn exposed(&self, points: &[Point]) -> usize {
+        let mut seen = vec![0u64; self.bits.len()];
+        let mut res = 0;
+        for p in points {
+            let Some(i) = self.index(p) else { continue };
+            if seen[i / 64] & (1 << (i % 64)) != 0 {
+                continue;
+            }
+            seen[i / 64] |= 1 << (i % 64);
+            res += p.neighbors().iter().filter(|n| !self.contains(n)).count();
+        }
+        res
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Faces {
+    All,
+    Exterior,
+}
+
+// Faces that belong to exactly one cube, with that cube. Exterior faces are
+// the ones that can be reached from outside the droplet.
+fn exposed_faces(points: &[Point], faces: Faces) -> Vec<(Surface, Point)> {
+    let cubes = points.iter().copied().collect::<HashSet<_>>();
+    let mut owners: HashMap<Surface, Vec<Point>> = HashMap::new();
+    for p in &cubes {
+        for f in p.faces() {
+            owners.entry(f).or_default().push(*p);
+        }
+    }
+    let outside = match faces {
+        Faces::All => None,
+        Faces::Exterior => Some(exterior(points)),
+    };
+    let mut res = owners
+        .into_iter()
+        .filter(|(_, ps)| ps.len() == 1)
+        .map(|(f, ps)| (f, ps[0]))
+        .filter(|(f, p)| outside.as_ref().is_none_or(|o| o.contains(&f.beyond(p))))
+        .collect::<Vec<_>>();
+    res.sort();
+    res
+}
+
+// The air cells connected to the outside, within the bounding box grown by
+// one in every direction.
+fn exterior(points: &[Point]) -> HashSet<Point> {
+    let cubes = points.iter().copied().collect::<HashSet<_>>();
+    let (lo, hi) = match bounds(points) {
+        Some((lo, hi)) => (lo.offset(-1, -1, -1), hi.offset(1, 1, 1)),
+        None => return HashSet::new(),
+    };
+    let inside = |p: &Point| {
+        (lo.x..=hi.x).contains(&p.x) && (lo.y..=hi.y).contains(&p.y) && (lo.z..=hi.z).contains(&p.z)
+    };
+    let mut seen = HashSet::from([lo]);
+    let mut todo = vec![lo];
+    while let Some(p) = todo.pop() {
+        for n in p.neighbors() {
+            if inside(&n) && !cubes.contains(&n) && seen.insert(n) {
+                todo.push(n);
+            }
+        }
+    }
+    seen
+}
+
+fn bounds(points: &[Point]) -> Option<(Point, Point)> {
+    let first = points.first()?;
+    Some(points.iter().fold((*first, *first), |(lo, hi), p| {
+        (
+            Point {
+                x: lo.x.min(p.x),
+                y: lo.y.min(p.y),
+                z: lo.z.min(p.z),
+            },
+            Point {
+                x: hi.x.max(p.x),
+                y: hi.y.max(p.y),
+                z: hi.z.max(p.z),
+            },
+        )
+    }))
+}
+
+#[derive(Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<[isize; 3]>,
+    // vertex indices, counter-clockwise when seen from outside
+    pub triangles: Vec<[usize; 3]>,
+    pub normals: Vec<[isize; 3]>,
+}
+
+pub fn mesh(p: &Path, faces: Faces) -> Result<Mesh> {
+    let mut mesh = Mesh::default();
+    let mut index: HashMap<[isize; 3], usize> = HashMap::new();
+    for (f, cube) in exposed_faces(&load(p)?, faces) {
+        let (corners, normal) = f.corners(&cube);
+        let ids = corners.map(|c| {
+            *index.entry(c).or_insert_with(|| {
+                mesh.vertices.push(c);
+                mesh.vertices.len() - 1
+            })
+        });
+        for t in [[ids[0], ids[1], ids[2]], [ids[0], ids[2], ids[3]]] {
+            mesh.triangles.push(t);
+            mesh.normals.push(normal);
+        }
+    }
+    Ok(mesh)
+}
+
+impl Mesh {
+    pub fn write_stl<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "solid droplet")?;
+        for (t, n) in self.triangles.iter().zip(&self.normals) {
+            writeln!(w, "  facet normal {} {} {}", n[0], n[1], n[2])?;
+            writeln!(w, "    outer loop")?;
+            for &i in t {
+                let [x, y, z] = self.vertices[i];
+                writeln!(w, "      vertex {} {} {}", x, y, z)?;
+            }
+            writeln!(w, "    endloop")?;
+            writeln!(w, "  endfacet")?;
+        }
+        writeln!(w, "endsolid droplet")
+    }
+
+    pub fn write_obj<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for [x, y, z] in &self.vertices {
+            writeln!(w, "v {} {} {}", x, y, z)?;
+        }
+        for [a, b, c] in &self.triangles {
+            writeln!(w, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Analysis {
+    pub volume: usize,
+    // inclusive minimum and maximum cube coordinates
+    pub bounding_box: Option<([isize; 3], [isize; 3])>,
+    pub face_components: usize,
+    pub edge_components: usize,
+    pub euler_characteristic: isize,
+    pub cavities: usize,
+    pub tunnels: usize,
+}
+
+pub fn analyze(p: &Path) -> Result<Analysis> {
+    Ok(analyze_points(&load(p)?))
+}
+
+// The droplet is taken as the union of closed unit cubes. Its Euler
+// characteristic V - E + F - C equals b0 - b1 + b2, where b0 counts pieces
+// touching in at least a corner, b2 counts enclosed air pockets and b1
+// counts tunnels.
+fn analyze_points(points: &[Point]) -> Analysis {
+    let cubes = points.iter().copied().collect::<HashSet<_>>();
+    let (mut vertices, mut edges, mut faces) = (HashSet::new(), HashSet::new(), HashSet::new());
+    for p in &cubes {
+        for (dx, dy, dz) in corners() {
+            vertices.insert(p.offset(dx, dy, dz));
+        }
+        // edges are keyed by their lower end and the axis they run along
+        for (a, b) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            edges.insert((p.offset(0, a, b), 0));
+            edges.insert((p.offset(a, 0, b), 1));
+            edges.insert((p.offset(a, b, 0), 2));
+        }
+        faces.extend(p.faces());
+    }
+    let euler = vertices.len() as isize - edges.len() as isize + faces.len() as isize
+        - cubes.len() as isize;
+
+    let outside = exterior(points);
+    let pockets = match bounds(points) {
+        Some((lo, hi)) => (lo.x..=hi.x)
+            .flat_map(|x| {
+                (lo.y..=hi.y).flat_map(move |y| (lo.z..=hi.z).map(move |z| Point { x, y, z }))
+            })
+            .filter(|p| !cubes.contains(p) && !outside.contains(p))
+            .collect(),
+        None => HashSet::new(),
+    };
+    let cavities = components(&pockets, 1);
+    let pieces = components(&cubes, 3);
+    Analysis {
+        volume: cubes.len(),
+        bounding_box: bounds(points).map(|(lo, hi)| ([lo.x, lo.y, lo.z], [hi.x, hi.y, hi.z])),
+        face_components: components(&cubes, 1),
+        edge_components: components(&cubes, 2),
+        euler_characteristic: euler,
+        cavities,
+        tunnels: (pieces as isize + cavities as isize - euler) as usize,
+    }
+}
+
+// The offsets of the eight corners of a unit cube.
+fn corners() -> impl Iterator<Item = (isize, isize, isize)> {
+    (0..8).map(|i| (i & 1, (i >> 1) & 1, (i >> 2) & 1))
+}
+
+// Counts connected components, where cells are adjacent if they differ by
+// one in at most `axes` coordinates: 1 for faces, 2 for edges, 3 for corners.
+fn components(cells: &HashSet<Point>, axes: usize) -> usize {
+    let deltas = (-1..=1)
+        .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+        .filter(|&(dx, dy, dz)| {
+            let n = [dx, dy, dz].iter().filter(|d| **d != 0).count();
+            n > 0 && n <= axes
+        })
+        .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let mut count = 0;
+    for start in cells {
+        if !seen.insert(*start) {
+            continue;
+        }
+        count += 1;
+        let mut todo = vec![*start];
+        while let Some(p) = todo.pop() {
+            for &(dx, dy, dz) in &deltas {
+                let n = p.offset(dx, dy, dz);
+                if cells.contains(&n) && seen.insert(n) {
+                    todo.push(n);
+                }
+            }
+        }
+    }
+    count
+}
+
 fn load(p: &Path) -> Result<Vec<Point>> {
     Ok(File::open(p)
         .map_err(io::Error::into)
@@ -45,7 +353,55 @@ struct Point {
     z: isize,
 }
 
+impl Surface {
+    // The cell on the other side of the face, seen from the owning cube.
+    fn beyond(&self, cube: &Point) -> Point {
+        let d = if self.origin == *cube { -1 } else { 1 };
+        match self.dimension {
+            Dimension::XY => cube.offset(0, 0, d),
+            Dimension::YZ => cube.offset(d, 0, 0),
+            Dimension::XZ => cube.offset(0, d, 0),
+        }
+    }
+
+    // The corners of the face, ordered counter-clockwise around the normal
+    // that points away from the owning cube, and that normal.
+    fn corners(&self, cube: &Point) -> ([[isize; 3]; 4], [isize; 3]) {
+        let o = self.origin;
+        let c = |dx, dy, dz| [o.x + dx, o.y + dy, o.z + dz];
+        let (mut corners, normal) = match self.dimension {
+            Dimension::XY => ([c(0, 0, 0), c(1, 0, 0), c(1, 1, 0), c(0, 1, 0)], [0, 0, 1]),
+            Dimension::YZ => ([c(0, 0, 0), c(0, 1, 0), c(0, 1, 1), c(0, 0, 1)], [1, 0, 0]),
+            Dimension::XZ => ([c(0, 0, 0), c(0, 0, 1), c(1, 0, 1), c(1, 0, 0)], [0, 1, 0]),
+        };
+        if self.origin == *cube {
+            corners.reverse();
+            return (corners, normal.map(|n: isize| -n));
+        }
+        (corners, normal)
+    }
+}
+
 impl Point {
+    fn offset(&self, dx: isize, dy: isize, dz: isize) -> Point {
+        Point {
+            x: self.x + dx,
+            y: self.y + dy,
+            z: self.z + dz,
+        }
+    }
+
+    fn neighbors(&self) -> [Point; 6] {
+        [
+            self.offset(-1, 0, 0),
+            self.offset(1, 0, 0),
+            self.offset(0, -1, 0),
+            self.offset(0, 1, 0),
+            self.offset(0, 0, -1),
+            self.offset(0, 0, 1),
+        ]
+    }
+
     fn faces(&self) -> Vec<Surface> {
         vec![
             Surface {
@@ -119,6 +475,21 @@ mod tests {
     fn day18_part1_input() {
         assert_eq!(compute1(Path::new(INPUT)).unwrap(), 3454);
     }
+    #[test]
+    fn day18_part1_grid() {
+        assert_eq!(compute1_grid(Path::new(EXAMPLE)).unwrap(), 64);
+        assert_eq!(compute1_grid(Path::new(INPUT)).unwrap(), 3454);
+        let p = |x, y, z| Point { x, y, z };
+        // spread out cubes take the sparse path
+        let far = [p(0, 0, 0), p(1, 0, 0), p(1_000_000, 0, -1_000_000)];
+        assert!(Grid::new(&far).is_none());
+        assert_eq!(count_exposed(&far), 16);
+        assert_eq!(count_exposed(&[far[0], far[2], far[0]]), 12);
+        let near = [p(0, 0, 0), p(1, 0, 0), p(1, 0, 0), p(1, 1, 1)];
+        assert_eq!(Grid::new(&near).unwrap().exposed(&near), 16);
+        assert_eq!(count_exposed(&near), 16);
+    }
+
     // #[test]
     // fn day18_part2_example() {
     //     assert_eq!(compute2(Path::new(EXAMPLE), 0..=20).unwrap(), 56000011);
@@ -131,4 +502,98 @@ mod tests {
     //         11600823139120
     //     );
     // }
+
+    // Six times the volume enclosed by the mesh, by the divergence theorem.
+    fn volume6(m: &Mesh) -> isize {
+        m.triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| m.vertices[i]);
+                a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0])
+            })
+            .sum()
+    }
+
+    #[test]
+    fn day18_mesh() {
+        // a cube listed twice still closes off the bar
+        let path = std::env::temp_dir().join("day18_mesh_duplicates.txt");
+        std::fs::write(&path, "1,1,1\n1,1,1\n2,1,1\n").unwrap();
+        let bar = mesh(&path, Faces::All).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bar.triangles.len(), 2 * 10);
+        assert_eq!(volume6(&bar), 6 * 2);
+
+        let all = mesh(Path::new(EXAMPLE), Faces::All).unwrap();
+        assert_eq!(all.triangles.len(), 2 * 64);
+        assert_eq!(volume6(&all), 6 * 13);
+        // the exterior hull also encloses the trapped air cell
+        let exterior = mesh(Path::new(EXAMPLE), Faces::Exterior).unwrap();
+        assert_eq!(exterior.triangles.len(), 2 * 58);
+        assert_eq!(volume6(&exterior), 6 * 14);
+        for (t, n) in all.triangles.iter().zip(&all.normals) {
+            let [a, b, c] = t.map(|i| all.vertices[i]);
+            let (u, v) = (
+                [0, 1, 2].map(|i| b[i] - a[i]),
+                [0, 1, 2].map(|i| c[i] - a[i]),
+            );
+            let cross = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            assert_eq!(cross, *n);
+        }
+
+        let mut stl = Vec::new();
+        all.write_stl(&mut stl).unwrap();
+        let stl = String::from_utf8(stl).unwrap();
+        assert_eq!(stl.matches("facet normal").count(), 128);
+        assert!(stl.starts_with("solid droplet\n  facet normal"));
+        assert!(stl.trim_end().ends_with("endsolid droplet"));
+
+        let mut obj = Vec::new();
+        exterior.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(
+            obj.lines().filter(|l| l.starts_with("v ")).count(),
+            exterior.vertices.len()
+        );
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 116);
+    }
+
+    #[test]
+    fn day18_analyze_example() {
+        assert_eq!(
+            analyze(Path::new(EXAMPLE)).unwrap(),
+            Analysis {
+                volume: 13,
+                bounding_box: Some(([1, 1, 1], [3, 3, 6])),
+                face_components: 6,
+                edge_components: 1,
+                euler_characteristic: 2,
+                cavities: 1,
+                tunnels: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn day18_analyze_shapes() {
+        let p = |x, y, z| Point { x, y, z };
+        // a flat ring of eight cubes has one tunnel
+        let ring = (0..3)
+            .flat_map(|x| (0..3).map(move |y| p(x, y, 0)))
+            .filter(|c| *c != p(1, 1, 0))
+            .collect::<Vec<_>>();
+        let a = analyze_points(&ring);
+        assert_eq!((a.euler_characteristic, a.tunnels, a.cavities), (0, 1, 0));
+        // two cubes touching in a corner are one piece, but not face or edge connected
+        let a = analyze_points(&[p(0, 0, 0), p(1, 1, 1)]);
+        assert_eq!((a.face_components, a.edge_components), (2, 2));
+        assert_eq!((a.euler_characteristic, a.tunnels), (1, 0));
+        let a = analyze_points(&[]);
+        assert_eq!((a.volume, a.bounding_box, a.tunnels), (0, None, 0));
+    }
 }
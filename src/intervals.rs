@@ -0,0 +1,172 @@
+use std::cmp::{max, min};
+use std::ops::RangeInclusive;
+
+// A set of integers, stored as sorted, disjoint and non-adjacent ranges.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    ranges: Vec<RangeInclusive<isize>>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        IntervalSet::default()
+    }
+
+    pub fn insert(&mut self, r: RangeInclusive<isize>) {
+        if r.is_empty() {
+            return;
+        }
+        let (mut lo, mut hi) = r.into_inner();
+        // ranges that overlap or touch r are merged into it
+        let first = self
+            .ranges
+            .partition_point(|s| s.end().saturating_add(1) < lo);
+        let last = self
+            .ranges
+            .partition_point(|s| *s.start() <= hi.saturating_add(1));
+        if first < last {
+            lo = min(lo, *self.ranges[first].start());
+            hi = max(hi, *self.ranges[last - 1].end());
+        }
+        self.ranges.splice(first..last, [lo..=hi]);
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<isize>] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Saturates at usize::MAX, which only the full isize range exceeds.
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|r| r.end().abs_diff(*r.start()).saturating_add(1))
+            .fold(0, usize::saturating_add)
+    }
+
+    pub fn contains(&self, x: isize) -> bool {
+        let i = self.ranges.partition_point(|r| *r.end() < x);
+        self.ranges.get(i).is_some_and(|r| r.contains(&x))
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut res = self.clone();
+        for r in &other.ranges {
+            res.insert(r.clone());
+        }
+        res
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut res = IntervalSet::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let lo = max(a.start(), b.start());
+            let hi = min(a.end(), b.end());
+            if lo <= hi {
+                res.ranges.push(*lo..=*hi);
+            }
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        res
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        match (self.ranges.first(), self.ranges.last()) {
+            (Some(first), Some(last)) => {
+                self.intersection(&other.complement(*first.start()..=*last.end()))
+            }
+            _ => IntervalSet::new(),
+        }
+    }
+
+    pub fn complement(&self, bounds: RangeInclusive<isize>) -> IntervalSet {
+        let mut res = IntervalSet::new();
+        if bounds.is_empty() {
+            return res;
+        }
+        let mut next = Some(*bounds.start());
+        for r in &self.ranges {
+            let Some(n) = next else { break };
+            if n < *r.start() {
+                res.ranges.push(n..=min(r.start() - 1, *bounds.end()));
+            }
+            next = r.end().checked_add(1).map(|e| max(n, e));
+        }
+        if let Some(n) = next.filter(|n| n <= bounds.end()) {
+            res.ranges.push(n..=*bounds.end());
+        }
+        res.ranges.retain(|r| !r.is_empty());
+        res
+    }
+}
+
+impl FromIterator<RangeInclusive<isize>> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = RangeInclusive<isize>>>(iter: T) -> Self {
+        let mut res = IntervalSet::new();
+        for r in iter {
+            res.insert(r);
+        }
+        res
+    }
+}
+
+impl From<RangeInclusive<isize>> for IntervalSet {
+    fn from(r: RangeInclusive<isize>) -> Self {
+        IntervalSet::from_iter([r])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(rs: &[RangeInclusive<isize>]) -> IntervalSet {
+        rs.iter().cloned().collect()
+    }
+
+    #[test]
+    fn insert_merges() {
+        let s = set(&[
+            5..=7,
+            1..=2,
+            3..=3,
+            10..=12,
+            9..=9,
+            RangeInclusive::new(20, 19),
+        ]);
+        assert_eq!(s.ranges(), &[1..=3, 5..=7, 9..=12]);
+        assert_eq!(s.len(), 10);
+        assert!(s.contains(3) && s.contains(9) && !s.contains(4) && !s.contains(13));
+        assert_eq!(set(&[1..=10, 3..=4]).ranges(), &[1..=10]);
+        assert_eq!(set(&[3..=4, 6..=7, 0..=20]).ranges(), &[0..=20]);
+        assert!(set(&[]).is_empty());
+        assert_eq!(set(&[isize::MIN..=isize::MAX]).len(), usize::MAX);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = set(&[0..=10, 20..=30]);
+        let b = set(&[5..=25, 40..=50]);
+        assert_eq!(a.union(&b).ranges(), &[0..=30, 40..=50]);
+        assert_eq!(a.intersection(&b).ranges(), &[5..=10, 20..=25]);
+        assert_eq!(a.difference(&b).ranges(), &[0..=4, 26..=30]);
+        assert_eq!(b.difference(&a).ranges(), &[11..=19, 40..=50]);
+        assert_eq!(a.complement(-5..=35).ranges(), &[-5..=-1, 11..=19, 31..=35]);
+        assert_eq!(a.complement(2..=8).ranges(), &[]);
+        assert_eq!(a.complement(12..=15).ranges(), &[12..=15]);
+        assert_eq!(
+            set(&[0..=isize::MAX])
+                .complement(isize::MIN..=isize::MAX)
+                .ranges(),
+            &[isize::MIN..=-1]
+        );
+    }
+}
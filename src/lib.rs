@@ -13,3 +13,4 @@ pub mod day12;
 pub mod day13;
 pub mod day15;
 pub mod day18;
+pub mod intervals;